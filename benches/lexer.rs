@@ -0,0 +1,49 @@
+//! Tokenizes generated sources of several megabytes and prints the
+//! throughput. Run with `cargo bench --bench lexer`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rose::lexer::Lexer;
+
+// Covers every token kind the lexer returns outside trivia mode, including
+// non-ASCII text in literals.
+const SNIPPET: &str = "/// Scales the area of a rectangle.
+func area(w: f32, h: f64, n: i32, m: i64, k: u32, l: u64, c: char, b: bool) -> f32 {
+    let result = w * h + 0x1F - 0o17 + 0b1010 + 1_000u64 + 2.5e3f64 + 7i64 + 3u32; // rounded
+    if result >= 100.25 && !(w == h) || h != 0 {
+        print \"grö\u{df}er als hundert: é\\n\";
+    } else {
+        print 'é', r#\"raw \"text\"\"#;
+    }
+    for n <= 10 { if n > 2 { continue; } break; }
+    /* block /* nested */ comment */
+    while result < [1, 2, 3].len && b == true || false { result = result / 2 - 1; }
+    return result;
+}
+";
+
+const RUNS: usize = 3;
+
+fn main() {
+    for megabytes in [1, 4, 16] {
+        let source = SNIPPET.repeat(megabytes * 1024 * 1024 / SNIPPET.len() + 1);
+
+        let mut best = Duration::MAX;
+        let mut token_count = 0;
+        for _ in 0..RUNS {
+            let mut lexer = Lexer::new(source.clone());
+            let start = Instant::now();
+            let (tokens, errors) = lexer.tokens_recovering();
+            best = best.min(start.elapsed());
+            token_count = black_box(tokens).len() + black_box(errors).len();
+        }
+
+        let size = source.len() as f64 / (1024.0 * 1024.0);
+        println!(
+            "{size:>6.1} MB {token_count:>10} tokens {:>10.2?} {:>8.1} MB/s",
+            best,
+            size / best.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::grammar::{Grammar, OutOfNonterminals, Rule, fresh_nonterminal};
+
+/// A deterministic automaton over terminal characters. Missing transitions
+/// go to an implicit dead state.
+pub struct Dfa {
+    pub start: usize,
+    pub accepting: Vec<bool>,
+    pub transitions: Vec<BTreeMap<char, usize>>,
+}
+
+// Thompson-style automaton built while parsing a pattern.
+struct Nfa {
+    epsilon: Vec<Vec<usize>>,
+    edges: Vec<Vec<(char, usize)>>,
+}
+
+struct PatternParser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    alphabet: &'a str,
+    nfa: Nfa,
+}
+
+// Right-hand side symbol of a rule in the product construction. A triple
+// (p, A, q) stands for the words derived from A that lead from p to q.
+#[derive(Clone)]
+enum ProductSymbol {
+    Terminal(char),
+    Triple(usize, char, usize),
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    UnbalancedParens { position: usize },
+    NothingToRepeat { position: usize },
+    DanglingEscape,
+}
+
+impl Dfa {
+    /// Builds a DFA from a regular expression over single characters.
+    ///
+    /// Supports literals, `.` (any character of `alphabet`), grouping with
+    /// `()`, alternation with `|`, the `*`, `+` and `?` operators, and `\`
+    /// to escape one of the special characters.
+    pub fn from_pattern(pattern: &str, alphabet: &str) -> Result<Dfa, PatternError> {
+        let mut parser = PatternParser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            alphabet,
+            nfa: Nfa {
+                epsilon: Vec::new(),
+                edges: Vec::new(),
+            },
+        };
+
+        let (start, end) = parser.alternation()?;
+        if parser.position < parser.chars.len() {
+            return Err(PatternError::UnbalancedParens {
+                position: parser.position,
+            });
+        }
+
+        Ok(parser.nfa.determinize(start, end))
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.accepting.len()
+    }
+
+    pub fn next(&self, state: usize, symbol: char) -> Option<usize> {
+        self.transitions[state].get(&symbol).copied()
+    }
+
+    pub fn accepts(&self, word: &str) -> bool {
+        let mut state = self.start;
+        for ch in word.chars() {
+            match self.next(state, ch) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.accepting[state]
+    }
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> usize {
+        self.epsilon.push(Vec::new());
+        self.edges.push(Vec::new());
+        self.epsilon.len() - 1
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut pending: Vec<usize> = states.into_iter().collect();
+
+        while let Some(state) = pending.pop() {
+            if closure.insert(state) {
+                pending.extend(&self.epsilon[state]);
+            }
+        }
+
+        closure
+    }
+
+    // Subset construction.
+    fn determinize(&self, start: usize, end: usize) -> Dfa {
+        let initial = self.closure([start]);
+        let mut ids = BTreeMap::from([(initial.clone(), 0)]);
+        let mut subsets = vec![initial];
+        let mut transitions = Vec::new();
+
+        let mut current = 0;
+        while current < subsets.len() {
+            let mut moves: BTreeMap<char, BTreeSet<usize>> = BTreeMap::new();
+            for &state in &subsets[current] {
+                for &(symbol, target) in &self.edges[state] {
+                    moves.entry(symbol).or_default().insert(target);
+                }
+            }
+
+            let mut row = BTreeMap::new();
+            for (symbol, targets) in moves {
+                let subset = self.closure(targets);
+                let id = match ids.get(&subset) {
+                    Some(&id) => id,
+                    None => {
+                        ids.insert(subset.clone(), subsets.len());
+                        subsets.push(subset);
+                        subsets.len() - 1
+                    }
+                };
+                row.insert(symbol, id);
+            }
+            transitions.push(row);
+            current += 1;
+        }
+
+        Dfa {
+            start: 0,
+            accepting: subsets.iter().map(|subset| subset.contains(&end)).collect(),
+            transitions,
+        }
+    }
+}
+
+// Each method returns the (start, end) states of the fragment it parsed.
+impl PatternParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn alternation(&mut self) -> Result<(usize, usize), PatternError> {
+        let mut branches = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            branches.push(self.concatenation()?);
+        }
+
+        if branches.len() == 1 {
+            return Ok(branches[0]);
+        }
+
+        let start = self.nfa.add_state();
+        let end = self.nfa.add_state();
+        for (branch_start, branch_end) in branches {
+            self.nfa.epsilon[start].push(branch_start);
+            self.nfa.epsilon[branch_end].push(end);
+        }
+        Ok((start, end))
+    }
+
+    fn concatenation(&mut self) -> Result<(usize, usize), PatternError> {
+        let start = self.nfa.add_state();
+        let mut end = start;
+
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let (piece_start, piece_end) = self.repetition()?;
+            self.nfa.epsilon[end].push(piece_start);
+            end = piece_end;
+        }
+
+        Ok((start, end))
+    }
+
+    fn repetition(&mut self) -> Result<(usize, usize), PatternError> {
+        let (mut start, mut end) = self.atom()?;
+
+        while let Some(op @ ('*' | '+' | '?')) = self.peek() {
+            self.position += 1;
+            let new_start = self.nfa.add_state();
+            let new_end = self.nfa.add_state();
+            self.nfa.epsilon[new_start].push(start);
+            self.nfa.epsilon[end].push(new_end);
+            if op != '+' {
+                self.nfa.epsilon[new_start].push(new_end);
+            }
+            if op != '?' {
+                self.nfa.epsilon[end].push(start);
+            }
+            start = new_start;
+            end = new_end;
+        }
+
+        Ok((start, end))
+    }
+
+    fn atom(&mut self) -> Result<(usize, usize), PatternError> {
+        let position = self.position;
+        let ch = self.peek().ok_or(PatternError::NothingToRepeat { position })?;
+        self.position += 1;
+
+        let symbols: Vec<char> = match ch {
+            '(' => {
+                let group = self.alternation()?;
+                if self.peek() != Some(')') {
+                    return Err(PatternError::UnbalancedParens { position });
+                }
+                self.position += 1;
+                return Ok(group);
+            }
+            '*' | '+' | '?' => return Err(PatternError::NothingToRepeat { position }),
+            '.' => self.alphabet.chars().collect(),
+            '\\' => {
+                let escaped = self.peek().ok_or(PatternError::DanglingEscape)?;
+                self.position += 1;
+                vec![escaped]
+            }
+            _ => vec![ch],
+        };
+
+        let start = self.nfa.add_state();
+        let end = self.nfa.add_state();
+        for symbol in symbols {
+            self.nfa.edges[start].push((symbol, end));
+        }
+        Ok((start, end))
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::UnbalancedParens { position } => {
+                write!(f, "unbalanced parenthesis at position {position}")
+            }
+            PatternError::NothingToRepeat { position } => {
+                write!(f, "nothing to repeat at position {position}")
+            }
+            PatternError::DanglingEscape => write!(f, "pattern ends with an escape"),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+impl Grammar {
+    /// Bar-Hillel product with a DFA: a grammar for the words of L(self) that
+    /// the automaton accepts, with useless rules removed. Fails if the useful
+    /// triples outnumber the uppercase characters available to name them.
+    ///
+    /// Nonempty words end in a virtual final state, entered by the last
+    /// character of any accepted word, so the start triples do not multiply
+    /// with the accepting states. The empty word is handled separately.
+    pub fn intersect(&self, dfa: &Dfa) -> Result<Grammar, OutOfNonterminals> {
+        let final_state = dfa.state_count();
+        let states = final_state + 1;
+        let targets = |state: usize, ch: char| -> Vec<usize> {
+            match (state < final_state).then(|| dfa.next(state, ch)).flatten() {
+                Some(to) if dfa.accepting[to] => vec![to, final_state],
+                Some(to) => vec![to],
+                None => Vec::new(),
+            }
+        };
+
+        let mut triple_rules: Vec<((usize, char, usize), Vec<ProductSymbol>)> = Vec::new();
+        for rule in &self.rules {
+            for from in 0..states {
+                let mut partial = vec![(from, Vec::new())];
+                for ch in rule.rhs.chars() {
+                    let mut extended = Vec::new();
+                    for (state, symbols) in partial {
+                        if ch.is_uppercase() {
+                            for to in 0..states {
+                                let mut symbols = symbols.clone();
+                                symbols.push(ProductSymbol::Triple(state, ch, to));
+                                extended.push((to, symbols));
+                            }
+                        } else {
+                            for to in targets(state, ch) {
+                                let mut symbols = symbols.clone();
+                                symbols.push(ProductSymbol::Terminal(ch));
+                                extended.push((to, symbols));
+                            }
+                        }
+                    }
+                    partial = extended;
+                }
+                for (to, symbols) in partial {
+                    triple_rules.push(((from, rule.lhs, to), symbols));
+                }
+            }
+        }
+
+        // Only give characters to triples that can generate a word and are
+        // reachable from a start triple, since there are far fewer of those
+        // than candidate triples.
+        let mut generating = BTreeSet::new();
+        loop {
+            let mut changed = false;
+            for (lhs, rhs) in &triple_rules {
+                if !generating.contains(lhs)
+                    && rhs.iter().all(|symbol| match *symbol {
+                        ProductSymbol::Terminal(_) => true,
+                        ProductSymbol::Triple(p, nt, q) => generating.contains(&(p, nt, q)),
+                    })
+                {
+                    generating.insert(*lhs);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let is_useful_rule = |rhs: &[ProductSymbol]| {
+            rhs.iter().all(|symbol| match *symbol {
+                ProductSymbol::Terminal(_) => true,
+                ProductSymbol::Triple(p, nt, q) => generating.contains(&(p, nt, q)),
+            })
+        };
+        let start_triple = (dfa.start, self.start, final_state);
+        let mut useful = BTreeSet::new();
+        if generating.contains(&start_triple) {
+            useful.insert(start_triple);
+        }
+        let mut pending: Vec<(usize, char, usize)> = useful.iter().copied().collect();
+        while let Some(triple) = pending.pop() {
+            for (lhs, rhs) in &triple_rules {
+                if *lhs != triple || !is_useful_rule(rhs) {
+                    continue;
+                }
+                for symbol in rhs {
+                    if let ProductSymbol::Triple(p, nt, q) = *symbol
+                        && useful.insert((p, nt, q))
+                    {
+                        pending.push((p, nt, q));
+                    }
+                }
+            }
+        }
+
+        let mut names = BTreeMap::new();
+        let mut used = String::new();
+        for &triple in &useful {
+            let name = fresh_nonterminal(&used).ok_or(OutOfNonterminals)?;
+            used.push(name);
+            names.insert(triple, name);
+        }
+        let start = fresh_nonterminal(&used).ok_or(OutOfNonterminals)?;
+
+        let mut rules = Vec::new();
+        if let Some(name) = names.get(&start_triple) {
+            rules.push(Rule::new(start, &name.to_string()));
+        }
+        if dfa.accepting[dfa.start] && self.nullable_nonterminals().contains(self.start) {
+            rules.push(Rule::new(start, ""));
+        }
+        for (lhs, rhs) in &triple_rules {
+            let Some(&lhs) = names.get(lhs) else { continue };
+            let rhs: Option<String> = rhs
+                .iter()
+                .map(|symbol| match *symbol {
+                    ProductSymbol::Terminal(ch) => Some(ch),
+                    ProductSymbol::Triple(p, nt, q) => names.get(&(p, nt, q)).copied(),
+                })
+                .collect();
+            if let Some(rhs) = rhs {
+                rules.push(Rule { lhs, rhs });
+            }
+        }
+
+        Ok(Grammar::with_start(start, rules).reduce())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every word over `alphabet` up to `max_len` characters.
+    fn all_words(alphabet: &str, max_len: usize) -> Vec<String> {
+        let mut words = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_len {
+            last = last
+                .iter()
+                .flat_map(|word| alphabet.chars().map(move |ch| format!("{word}{ch}")))
+                .collect();
+            words.extend(last.iter().cloned());
+        }
+        words
+    }
+
+    #[test]
+    fn intersection_with_regular_language() {
+        // Words over {a, b} whose second to last letter is an a.
+        let grammar = Grammar::parse("S -> aS\nS -> bS\nS ->\n").unwrap();
+        let dfa = Dfa::from_pattern("(a|b)*a(a|b)", &grammar.terminals).unwrap();
+        let product = grammar.intersect(&dfa).unwrap();
+
+        let expected: BTreeSet<String> = all_words("ab", 6)
+            .into_iter()
+            .filter(|word| word.chars().rev().nth(1) == Some('a'))
+            .collect();
+        assert_eq!(product.language_up_to(Some(6)), expected);
+    }
+
+    #[test]
+    fn intersection_with_context_free_language() {
+        // a^n b^n restricted to words with at least one a is a^n b^n, n >= 1.
+        let grammar = Grammar::parse("S -> aSb\nS ->\n").unwrap();
+        let dfa = Dfa::from_pattern("aa*bb*", &grammar.terminals).unwrap();
+        let product = grammar.intersect(&dfa).unwrap();
+
+        let expected: BTreeSet<String> = (1..=4)
+            .map(|n| format!("{}{}", "a".repeat(n), "b".repeat(n)))
+            .collect();
+        assert_eq!(product.language_up_to(Some(8)), expected);
+
+        let dfa = Dfa::from_pattern("aab", &grammar.terminals).unwrap();
+        assert!(grammar.intersect(&dfa).unwrap().rules.is_empty());
+    }
+
+    #[test]
+    fn intersection_keeps_empty_word() {
+        let grammar = Grammar::parse("S -> aSb\nS ->\n").unwrap();
+        let dfa = Dfa::from_pattern("(ab)*", &grammar.terminals).unwrap();
+        let product = grammar.intersect(&dfa).unwrap();
+
+        let expected = BTreeSet::from([String::new(), "ab".to_string()]);
+        assert_eq!(product.language_up_to(Some(6)), expected);
+    }
+
+    #[test]
+    fn intersection_with_many_accepting_states() {
+        // The DFA has 64 states, half of them accepting, which used to need
+        // more nonterminals than there are uppercase characters.
+        let grammar = Grammar::parse("S -> aS\nS -> bS\nS ->\n").unwrap();
+        let dfa =
+            Dfa::from_pattern("(a|b)*a(a|b)(a|b)(a|b)(a|b)(a|b)", &grammar.terminals).unwrap();
+        let product = grammar.intersect(&dfa).unwrap();
+
+        let words = product.language_up_to(Some(7));
+        assert!(words.contains("abbbbb"));
+        assert!(words.contains("babbbbb"));
+        assert!(!words.contains("bbbbbb"));
+        assert!(words.iter().all(|word| dfa.accepts(word)));
+    }
+}
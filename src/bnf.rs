@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::grammar::{Grammar, Rule, fresh_nonterminal};
+
+/// Which notation a grammar specification is written in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// `<expr> ::= <expr> "+" <term> | <term>`, one definition after another.
+    Bnf,
+    /// ISO-style `expr = term , { "+" , term } ;` with `{}`, `[]` and `()`.
+    Ebnf,
+}
+
+/// A grammar read from BNF or EBNF, with the character chosen for each name.
+pub struct Imported {
+    pub grammar: Grammar,
+    pub names: Vec<(String, char)>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    UnexpectedChar { line: usize, found: char },
+    UnterminatedLiteral { line: usize },
+    Expected { line: usize, expected: &'static str },
+    UnsupportedTerminal { line: usize, literal: String },
+    Undefined { line: usize, name: String },
+    NoRules,
+    TooManyNonterminals, // More names and helpers than uppercase characters
+}
+
+#[derive(Clone, PartialEq)]
+enum TokenKind {
+    Name(String),
+    Literal(String),
+    Define,
+    Bar,
+    Comma,
+    End,
+    Open(char),
+    Close(char),
+}
+
+struct Token {
+    kind: TokenKind,
+    line: usize,
+}
+
+// Right-hand sides before repetition and options are desugared.
+enum Expr {
+    Terminal(String),
+    Nonterminal(String),
+    Sequence(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Repeat(Box<Expr>),
+    Optional(Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    notation: Notation,
+}
+
+// Turns parsed definitions into single-character rules.
+struct Desugarer {
+    names: HashMap<String, char>,
+    used: String,
+    rules: Vec<Rule>,
+    helper_rules: Vec<Rule>,
+}
+
+/// Reads a BNF or EBNF specification. Every name becomes a nonterminal,
+/// preferably the uppercase first letter of the name, and the first
+/// definition is the start symbol. `{}` and `[]` become fresh nonterminals
+/// for zero-or-more and zero-or-one, and groups with alternatives become a
+/// fresh nonterminal with one rule per alternative. Quoted terminals are
+/// split into characters, so they may not contain uppercase letters or
+/// whitespace.
+pub fn import(source: &str, notation: Notation) -> Result<Imported, ImportError> {
+    let mut parser = Parser {
+        tokens: tokenize(source, notation)?,
+        position: 0,
+        notation,
+    };
+    let definitions = parser.definitions()?;
+    if definitions.is_empty() {
+        return Err(ImportError::NoRules);
+    }
+
+    let mut desugarer = Desugarer {
+        names: HashMap::new(),
+        used: String::new(),
+        rules: Vec::new(),
+        helper_rules: Vec::new(),
+    };
+    let mut order = Vec::new();
+    for (name, _, _) in &definitions {
+        if !desugarer.names.contains_key(name) {
+            desugarer.name(name)?;
+            order.push(name.clone());
+        }
+    }
+
+    for (name, expr, line) in &definitions {
+        let lhs = desugarer.names[name];
+        for alternative in alternatives(expr) {
+            let rhs = desugarer.symbols(alternative, *line)?;
+            desugarer.rules.push(Rule { lhs, rhs });
+        }
+    }
+
+    let start = desugarer.names[&definitions[0].0];
+    desugarer.rules.append(&mut desugarer.helper_rules);
+    let names = order
+        .into_iter()
+        .map(|name| {
+            let ch = desugarer.names[&name];
+            (name, ch)
+        })
+        .collect();
+    Ok(Imported {
+        grammar: Grammar::with_start(start, desugarer.rules),
+        names,
+    })
+}
+
+fn tokenize(source: &str, notation: Notation) -> Result<Vec<Token>, ImportError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        let kind = match ch {
+            '\n' => {
+                line += 1;
+                i += 1;
+                continue;
+            }
+            _ if ch.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' if notation == Notation::Ebnf && chars.get(i + 1) == Some(&'*') => {
+                // (* comment *)
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&')')) {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            '"' | '\'' => {
+                let start_line = line;
+                let mut literal = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != ch {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    literal.push(chars[i]);
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(ImportError::UnterminatedLiteral { line: start_line });
+                }
+                TokenKind::Literal(literal)
+            }
+            '<' if notation == Notation::Bnf => {
+                let mut name = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != '>' && chars[i] != '\n' {
+                    name.push(chars[i]);
+                    i += 1;
+                }
+                if chars.get(i) != Some(&'>') {
+                    return Err(ImportError::Expected { line, expected: ">" });
+                }
+                TokenKind::Name(name.trim().to_string())
+            }
+            ':' if chars[i..].starts_with(&[':', ':', '=']) => {
+                i += 2;
+                TokenKind::Define
+            }
+            '=' if notation == Notation::Ebnf => TokenKind::Define,
+            '|' => TokenKind::Bar,
+            ',' if notation == Notation::Ebnf => TokenKind::Comma,
+            ';' | '.' if notation == Notation::Ebnf => TokenKind::End,
+            '(' | '{' | '[' if notation == Notation::Ebnf => TokenKind::Open(ch),
+            ')' | '}' | ']' if notation == Notation::Ebnf => TokenKind::Close(ch),
+            _ if notation == Notation::Ebnf && (ch.is_alphanumeric() || ch == '_') => {
+                let mut name = String::new();
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    name.push(chars[i]);
+                    i += 1;
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Name(name),
+                    line,
+                });
+                continue;
+            }
+            _ => return Err(ImportError::UnexpectedChar { line, found: ch }),
+        };
+
+        tokens.push(Token { kind, line });
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |token| token.line)
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<(), ImportError> {
+        if self.peek() != Some(&kind) {
+            return Err(ImportError::Expected {
+                line: self.line(),
+                expected,
+            });
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // Every `name ::= expression` in order, with the line it starts on.
+    fn definitions(&mut self) -> Result<Vec<(String, Expr, usize)>, ImportError> {
+        let mut definitions = Vec::new();
+
+        while self.peek().is_some() {
+            let line = self.line();
+            let Some(TokenKind::Name(name)) = self.peek().cloned() else {
+                return Err(ImportError::Expected {
+                    line,
+                    expected: "a rule name",
+                });
+            };
+            self.position += 1;
+            self.expect(TokenKind::Define, "::= or =")?;
+
+            let expr = self.choice()?;
+            if self.notation == Notation::Ebnf {
+                self.expect(TokenKind::End, "; at the end of the rule")?;
+            }
+            definitions.push((name, expr, line));
+        }
+
+        Ok(definitions)
+    }
+
+    fn choice(&mut self) -> Result<Expr, ImportError> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some(&TokenKind::Bar) {
+            self.position += 1;
+            alternatives.push(self.sequence()?);
+        }
+
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Expr::Choice(alternatives))
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Expr, ImportError> {
+        let mut items = Vec::new();
+
+        loop {
+            match self.peek().cloned() {
+                Some(TokenKind::Comma) => self.position += 1,
+                Some(TokenKind::Literal(literal)) => {
+                    self.position += 1;
+                    items.push(Expr::Terminal(literal));
+                }
+                Some(TokenKind::Name(name)) => {
+                    // In BNF the next definition starts without a terminator.
+                    if self.notation == Notation::Bnf
+                        && self.tokens.get(self.position + 1).map(|token| &token.kind)
+                            == Some(&TokenKind::Define)
+                    {
+                        break;
+                    }
+                    self.position += 1;
+                    items.push(Expr::Nonterminal(name));
+                }
+                Some(TokenKind::Open(open)) => {
+                    self.position += 1;
+                    let inner = self.choice()?;
+                    let (close, expected) = match open {
+                        '{' => ('}', "}"),
+                        '[' => (']', "]"),
+                        _ => (')', ")"),
+                    };
+                    self.expect(TokenKind::Close(close), expected)?;
+                    items.push(match open {
+                        '{' => Expr::Repeat(Box::new(inner)),
+                        '[' => Expr::Optional(Box::new(inner)),
+                        _ => inner,
+                    });
+                }
+                _ => break,
+            }
+        }
+
+        Ok(Expr::Sequence(items))
+    }
+}
+
+impl Desugarer {
+    fn name(&mut self, name: &str) -> Result<char, ImportError> {
+        if let Some(&ch) = self.names.get(name) {
+            return Ok(ch);
+        }
+
+        let preferred = name
+            .chars()
+            .next()
+            .and_then(|first| first.to_uppercase().next())
+            .filter(|ch| ch.is_uppercase() && !self.used.contains(*ch));
+        let ch = preferred
+            .or_else(|| fresh_nonterminal(&self.used))
+            .ok_or(ImportError::TooManyNonterminals)?;
+        self.used.push(ch);
+        self.names.insert(name.to_string(), ch);
+        Ok(ch)
+    }
+
+    // A fresh nonterminal with one rule per alternative.
+    fn helper(&mut self, alternatives: Vec<String>) -> Result<char, ImportError> {
+        let ch = fresh_nonterminal(&self.used).ok_or(ImportError::TooManyNonterminals)?;
+        self.used.push(ch);
+        for rhs in alternatives {
+            self.helper_rules.push(Rule { lhs: ch, rhs });
+        }
+        Ok(ch)
+    }
+
+    fn symbols(&mut self, expr: &Expr, line: usize) -> Result<String, ImportError> {
+        Ok(match expr {
+            Expr::Terminal(literal) => {
+                if literal
+                    .chars()
+                    .any(|ch| ch.is_uppercase() || ch.is_whitespace())
+                {
+                    return Err(ImportError::UnsupportedTerminal {
+                        line,
+                        literal: literal.clone(),
+                    });
+                }
+                literal.clone()
+            }
+            Expr::Nonterminal(name) => {
+                if !self.names.contains_key(name) {
+                    return Err(ImportError::Undefined {
+                        line,
+                        name: name.clone(),
+                    });
+                }
+                self.names[name].to_string()
+            }
+            Expr::Sequence(items) => {
+                let mut result = String::new();
+                for item in items {
+                    result.push_str(&self.symbols(item, line)?);
+                }
+                result
+            }
+            Expr::Choice(options) => {
+                let mut rhs = Vec::new();
+                for option in options {
+                    rhs.push(self.symbols(option, line)?);
+                }
+                self.helper(rhs)?.to_string()
+            }
+            Expr::Repeat(inner) => {
+                // R -> inner R | ε
+                let ch = fresh_nonterminal(&self.used).ok_or(ImportError::TooManyNonterminals)?;
+                self.used.push(ch);
+                let body = self.alternatives_of(inner, line)?;
+                for rhs in body {
+                    self.helper_rules.push(Rule {
+                        lhs: ch,
+                        rhs: format!("{rhs}{ch}"),
+                    });
+                }
+                self.helper_rules.push(Rule::new(ch, ""));
+                ch.to_string()
+            }
+            Expr::Optional(inner) => {
+                let mut rhs = self.alternatives_of(inner, line)?;
+                rhs.push(String::new());
+                self.helper(rhs)?.to_string()
+            }
+        })
+    }
+
+    fn alternatives_of(&mut self, expr: &Expr, line: usize) -> Result<Vec<String>, ImportError> {
+        let mut result = Vec::new();
+        for alternative in alternatives(expr) {
+            result.push(self.symbols(alternative, line)?);
+        }
+        Ok(result)
+    }
+}
+
+// Top-level alternatives of an expression.
+fn alternatives(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Choice(options) => options.iter().collect(),
+        _ => vec![expr],
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::UnexpectedChar { line, found } => {
+                write!(f, "line {line}: unexpected character {found:?}")
+            }
+            ImportError::UnterminatedLiteral { line } => {
+                write!(f, "line {line}: unterminated quoted terminal")
+            }
+            ImportError::Expected { line, expected } => write!(f, "line {line}: expected {expected}"),
+            ImportError::UnsupportedTerminal { line, literal } => write!(
+                f,
+                "line {line}: terminal {literal:?} contains uppercase letters or whitespace, which cannot be grammar terminals"
+            ),
+            ImportError::Undefined { line, name } => {
+                write!(f, "line {line}: {name} is used but never defined")
+            }
+            ImportError::NoRules => write!(f, "no rules found"),
+            ImportError::TooManyNonterminals => {
+                write!(f, "more nonterminals are needed than there are uppercase characters")
+            }
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(source: &str, notation: Notation) -> Vec<String> {
+        import(source, notation)
+            .unwrap()
+            .grammar
+            .rules
+            .iter()
+            .map(|rule| rule.display())
+            .collect()
+    }
+
+    #[test]
+    fn ebnf_desugaring() {
+        let source = "list = item , { \",\" , item } ;\nitem = [ \"-\" ] , ( \"a\" | \"b\" ) ;\n";
+        let imported = import(source, Notation::Ebnf).unwrap();
+        assert_eq!(imported.grammar.start, 'L');
+        assert_eq!(
+            imported.names,
+            [("list".to_string(), 'L'), ("item".to_string(), 'I')]
+        );
+
+        // {} repeats through a helper, [] adds an empty alternative and ()
+        // becomes a helper with one rule per alternative.
+        assert_eq!(
+            rules(source, Notation::Ebnf),
+            [
+                "L -> IA", "I -> BC", "A -> ,IA", "A -> ", "B -> -", "B -> ", "C -> a", "C -> b"
+            ]
+        );
+    }
+
+    #[test]
+    fn ebnf_group_without_alternatives() {
+        assert_eq!(
+            rules("s = ( \"a\" , \"b\" ) , \"c\" ;", Notation::Ebnf),
+            ["S -> abc"]
+        );
+    }
+
+    #[test]
+    fn bnf_without_terminators() {
+        let source =
+            "<expr> ::= <expr> \"+\" <term> | <term>\n<term> ::= \"a\"\n  | \"(\" <expr> \")\"\n";
+        assert_eq!(
+            rules(source, Notation::Bnf),
+            ["E -> E+T", "E -> T", "T -> a", "T -> (E)"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            import("<s> ::= \"a\"\n<t> ::= <u>\n", Notation::Bnf),
+            Err(ImportError::Undefined { line: 2, name }) if name == "u"
+        ));
+        assert!(matches!(
+            import("s = \"A\" ;", Notation::Ebnf),
+            Err(ImportError::UnsupportedTerminal { line: 1, .. })
+        ));
+        assert!(matches!(
+            import("s = { \"a\" ;", Notation::Ebnf),
+            Err(ImportError::Expected {
+                line: 1,
+                expected: "}"
+            })
+        ));
+        assert!(matches!(
+            import("", Notation::Bnf),
+            Err(ImportError::NoRules)
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::grammar::{Grammar, Rule};
+
+/// Longest words compared when looking for sample words.
+pub const SAMPLE_LENGTH: usize = 6;
+
+// Upper bound on the words formed while enumerating each grammar's language,
+// so grammars with many or very ambiguous words are compared up to a shorter
+// length instead of keeping `diff` busy.
+const WORK_LIMIT: usize = 200_000;
+const SAMPLE_COUNT: usize = 10;
+
+/// Structural and language differences between an old and a new grammar.
+/// Rules are paired with their index in the grammar they come from.
+#[derive(Serialize)]
+pub struct GrammarDiff {
+    pub start: Option<(char, char)>, // (old, new) if the start symbol changed
+    pub removed: Vec<(usize, Rule)>,
+    pub added: Vec<(usize, Rule)>,
+    pub changed: Vec<RuleChange>,
+    pub removed_terminals: String,
+    pub added_terminals: String,
+    pub removed_nonterminals: String,
+    pub added_nonterminals: String,
+    pub sample_length: Option<usize>, // None if words were not compared
+    pub only_old: Vec<String>,
+    pub only_new: Vec<String>,
+}
+
+/// A rule whose rhs was rewritten, matched by lhs.
+#[derive(Serialize)]
+pub struct RuleChange {
+    pub old: (usize, Rule),
+    pub new: (usize, Rule),
+}
+
+impl GrammarDiff {
+    pub fn new(old: &Grammar, new: &Grammar) -> GrammarDiff {
+        // Pair identical rules first, then leftover rules with the same lhs.
+        let mut unmatched_new: Vec<usize> = (0..new.rules.len()).collect();
+        let mut unmatched_old = Vec::new();
+        for (i, rule) in old.rules.iter().enumerate() {
+            let same = unmatched_new
+                .iter()
+                .position(|&j| new.rules[j].lhs == rule.lhs && new.rules[j].rhs == rule.rhs);
+            match same {
+                Some(position) => {
+                    unmatched_new.remove(position);
+                }
+                None => unmatched_old.push(i),
+            }
+        }
+
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for i in unmatched_old {
+            let rule = &old.rules[i];
+            match unmatched_new.iter().position(|&j| new.rules[j].lhs == rule.lhs) {
+                Some(position) => {
+                    let j = unmatched_new.remove(position);
+                    changed.push(RuleChange {
+                        old: (i, rule.clone()),
+                        new: (j, new.rules[j].clone()),
+                    });
+                }
+                None => removed.push((i, rule.clone())),
+            }
+        }
+        let added = unmatched_new
+            .into_iter()
+            .map(|j| (j, new.rules[j].clone()))
+            .collect();
+
+        // Compare up to the longest length both grammars enumerate in time.
+        let old_words = old.words_by_length(SAMPLE_LENGTH, WORK_LIMIT);
+        let new_words = new.words_by_length(SAMPLE_LENGTH, WORK_LIMIT);
+        let compared = old_words.len().min(new_words.len());
+        let sample_length = compared.checked_sub(1).filter(|&length| length > 0);
+        let (mut only_old, mut only_new) = (Vec::new(), Vec::new());
+        if sample_length.is_some() {
+            let old_words: BTreeSet<&String> = old_words[..compared].iter().flatten().collect();
+            let new_words: BTreeSet<&String> = new_words[..compared].iter().flatten().collect();
+            only_old = samples(old_words.difference(&new_words).copied());
+            only_new = samples(new_words.difference(&old_words).copied());
+        }
+
+        GrammarDiff {
+            start: (old.start != new.start).then_some((old.start, new.start)),
+            removed,
+            added,
+            changed,
+            removed_terminals: missing_from(&old.terminals, &new.terminals),
+            added_terminals: missing_from(&new.terminals, &old.terminals),
+            removed_nonterminals: missing_from(&old.nonterminals, &new.nonterminals),
+            added_nonterminals: missing_from(&new.nonterminals, &old.nonterminals),
+            sample_length,
+            only_old,
+            only_new,
+        }
+    }
+
+    /// True if the rules and start symbol are the same, in any order.
+    pub fn is_structurally_equal(&self) -> bool {
+        self.start.is_none()
+            && self.removed.is_empty()
+            && self.added.is_empty()
+            && self.changed.is_empty()
+    }
+
+    pub fn display(&self) -> String {
+        if self.is_structurally_equal() {
+            return "The grammars have the same rules.\n".to_string();
+        }
+
+        let mut result = String::new();
+        if let Some((old, new)) = self.start {
+            result.push_str(&format!("Start symbol: {old} => {new}\n"));
+        }
+        if !self.removed.is_empty() {
+            result.push_str("Removed rules:\n");
+            for (i, rule) in &self.removed {
+                result.push_str(&format!("  - {i}: {}\n", rule.display()));
+            }
+        }
+        if !self.added.is_empty() {
+            result.push_str("Added rules:\n");
+            for (i, rule) in &self.added {
+                result.push_str(&format!("  + {i}: {}\n", rule.display()));
+            }
+        }
+        if !self.changed.is_empty() {
+            result.push_str("Changed rules:\n");
+            for change in &self.changed {
+                result.push_str(&format!(
+                    "  ~ {}: {}  =>  {}: {}\n",
+                    change.old.0,
+                    change.old.1.display(),
+                    change.new.0,
+                    change.new.1.display()
+                ));
+            }
+        }
+
+        for (label, symbols) in [
+            ("Removed terminals", &self.removed_terminals),
+            ("Added terminals", &self.added_terminals),
+            ("Removed nonterminals", &self.removed_nonterminals),
+            ("Added nonterminals", &self.added_nonterminals),
+        ] {
+            if !symbols.is_empty() {
+                let symbols: Vec<String> = symbols.chars().map(|ch| ch.to_string()).collect();
+                result.push_str(&format!("{label}: {}\n", symbols.join(" ")));
+            }
+        }
+
+        let Some(length) = self.sample_length else {
+            result.push_str("The grammars have too many words to compare.\n");
+            return result;
+        };
+        if self.only_old.is_empty() && self.only_new.is_empty() {
+            result.push_str(&format!("Both generate the same words up to length {length}.\n"));
+        }
+        for (label, words) in [("old", &self.only_old), ("new", &self.only_new)] {
+            if !words.is_empty() {
+                let words: Vec<&str> = words
+                    .iter()
+                    .map(|word| if word.is_empty() { "(empty word)" } else { word })
+                    .collect();
+                result.push_str(&format!(
+                    "Only in {label}, up to length {length}: {}\n",
+                    words.join(", ")
+                ));
+            }
+        }
+        result
+    }
+}
+
+// The shortest words first.
+fn samples<'a>(words: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut words: Vec<String> = words.cloned().collect();
+    words.sort_by(|a, b| a.chars().count().cmp(&b.chars().count()).then(a.cmp(b)));
+    words.truncate(SAMPLE_COUNT);
+    words
+}
+
+fn missing_from(symbols: &str, other: &str) -> String {
+    symbols.chars().filter(|&ch| !other.contains(ch)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(source: &str) -> Grammar {
+        Grammar::parse(source).unwrap()
+    }
+
+    #[test]
+    fn pairs_rules() {
+        let old = grammar("S -> aS\nS -> b\nS -> c\n");
+        let new = grammar("S -> bb\nS -> aS\nT -> c\n");
+        let diff = GrammarDiff::new(&old, &new);
+
+        assert!(diff.start.is_none());
+        let pair = |(i, rule): &(usize, Rule)| (*i, rule.display());
+        let changed: Vec<_> = diff
+            .changed
+            .iter()
+            .map(|change| (pair(&change.old), pair(&change.new)))
+            .collect();
+        assert_eq!(
+            changed,
+            [((1, "S -> b".to_string()), (0, "S -> bb".to_string()))]
+        );
+        assert_eq!(
+            diff.removed.iter().map(pair).collect::<Vec<_>>(),
+            [(2, "S -> c".to_string())]
+        );
+        assert_eq!(
+            diff.added.iter().map(pair).collect::<Vec<_>>(),
+            [(2, "T -> c".to_string())]
+        );
+        assert_eq!(diff.added_nonterminals, "T");
+        assert_eq!(diff.removed_terminals, "");
+    }
+
+    #[test]
+    fn sample_words() {
+        let old = grammar("S -> aS\nS -> b\nS -> c\n");
+        let new = grammar("S -> aS\nS -> bb\nS -> c\n");
+        let diff = GrammarDiff::new(&old, &new);
+
+        assert_eq!(diff.sample_length, Some(SAMPLE_LENGTH));
+        assert_eq!(diff.only_old[..3], ["b", "ab", "aab"]);
+        assert_eq!(diff.only_new[..3], ["bb", "abb", "aabb"]);
+        assert!(diff.only_old.iter().all(|word| word.len() <= SAMPLE_LENGTH));
+        assert!(!diff.only_old.contains(&"c".to_string()));
+
+        let same = GrammarDiff::new(&old, &grammar("S -> b\nS -> c\nS -> aS\n"));
+        assert!(same.is_structurally_equal());
+        assert!(same.only_old.is_empty() && same.only_new.is_empty());
+    }
+
+    #[test]
+    fn work_limit_shortens_the_sample() {
+        // Every split of a word into SS forms it again, so the words up to
+        // SAMPLE_LENGTH take more than WORK_LIMIT to enumerate.
+        let ambiguous = grammar("S -> SS\nS -> a\nS -> b\nS -> c\nS -> d\nS -> e\n");
+        let diff = GrammarDiff::new(&ambiguous, &grammar("S -> aS\nS -> a\n"));
+        let length = diff.sample_length.unwrap();
+        assert!(length < SAMPLE_LENGTH);
+        assert!(diff.only_old.contains(&"b".to_string()));
+        assert!(diff.only_new.is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::grammar::{Associativity, Grammar};
+
+/// Name used for a nonterminal in generated files: the lowercase letter for
+/// `A`..`Z`, and `nt_` with the code point for any other character.
+pub fn rule_name(nonterminal: char) -> String {
+    if nonterminal.is_ascii_uppercase() {
+        nonterminal.to_ascii_lowercase().to_string()
+    } else {
+        format!("nt_{:04x}", nonterminal as u32)
+    }
+}
+
+/// A bison `.y` file with the grammar's rules and precedence declarations,
+/// plus a `yylex` that returns one character per token so it builds as is.
+pub fn bison(grammar: &Grammar) -> String {
+    let mut result = String::from(
+        "%{
+#include <ctype.h>
+#include <stdio.h>
+
+int yylex(void);
+void yyerror(const char *message);
+%}
+
+",
+    );
+
+    let named: Vec<String> = grammar
+        .terminals
+        .chars()
+        .filter(|ch| !ch.is_ascii())
+        .map(c_char)
+        .collect();
+    if !named.is_empty() {
+        result.push_str(&format!("%token {}\n", named.join(" ")));
+    }
+    for (associativity, terminals) in &grammar.precedence {
+        let keyword = match associativity {
+            Associativity::Left => "%left",
+            Associativity::Right => "%right",
+            Associativity::NonAssoc => "%nonassoc",
+        };
+        let terminals: Vec<String> = terminals.chars().map(c_char).collect();
+        result.push_str(&format!("{keyword} {}\n", terminals.join(" ")));
+    }
+    result.push_str(&format!("%start {}\n\n%%\n", rule_name(grammar.start)));
+
+    for nt in ordered_nonterminals(grammar) {
+        result.push_str(&format!("\n{}\n", rule_name(nt)));
+        for (i, rhs) in alternatives(grammar, nt).iter().enumerate() {
+            let body = if rhs.is_empty() {
+                "%empty".to_string()
+            } else {
+                symbols(rhs, c_char).join(" ")
+            };
+            let separator = if i == 0 { ':' } else { '|' };
+            result.push_str(&format!("    {separator} {body}\n"));
+        }
+        result.push_str("    ;\n");
+    }
+
+    result.push_str(
+        "
+%%
+
+int yylex(void) {
+    int c;
+    do {
+        c = getchar();
+    } while (isspace(c));
+    return c == EOF ? 0 : c;
+}
+
+void yyerror(const char *message) {
+    fprintf(stderr, \"%s\\n\", message);
+}
+
+int main(void) {
+    return yyparse();
+}
+",
+    );
+    result
+}
+
+/// An ANTLR4 `.g4` combined grammar. The start rule comes first and must
+/// consume the whole input; ANTLR has no precedence declarations, so those
+/// are listed in a comment.
+pub fn antlr(grammar: &Grammar, name: &str) -> String {
+    let mut result = format!("grammar {};\n", capitalize(name));
+    for (associativity, terminals) in &grammar.precedence {
+        result.push_str(&format!("// %{} {terminals}\n", associativity.keyword()));
+    }
+
+    result.push_str(&format!(
+        "\nstart_\n    : {} EOF\n    ;\n",
+        rule_name(grammar.start)
+    ));
+    for nt in ordered_nonterminals(grammar) {
+        result.push_str(&format!("\n{}\n", rule_name(nt)));
+        for (i, rhs) in alternatives(grammar, nt).iter().enumerate() {
+            let body = if rhs.is_empty() {
+                "/* empty */".to_string()
+            } else {
+                symbols(rhs, quoted).join(" ")
+            };
+            let separator = if i == 0 { ':' } else { '|' };
+            result.push_str(&format!("    {separator} {body}\n"));
+        }
+        result.push_str("    ;\n");
+    }
+
+    result.push_str("\nWS\n    : [ \\t\\r\\n]+ -> skip\n    ;\n");
+    result
+}
+
+/// A tree-sitter `grammar.js`. Alternatives of rules with a precedence are
+/// wrapped in `prec.left`/`prec.right`, one level per declaration line.
+///
+/// tree-sitter rejects rules other than the first that match the empty
+/// string, so ε alternatives are left out and nullable nonterminals are
+/// `optional` where they are used. A nullable start symbol is made optional
+/// by a `start_` rule in front of it.
+pub fn tree_sitter(grammar: &Grammar, name: &str) -> String {
+    let nullable = grammar.nullable_nonterminals();
+    let nonempty = nonempty_nonterminals(grammar);
+    // Nonterminals that only derive ε disappear along with their uses.
+    let only_empty = |ch: char| nullable.contains(ch) && !nonempty.contains(ch);
+
+    let mut result = format!(
+        "module.exports = grammar({{\n  name: '{}',\n\n  rules: {{\n",
+        name.to_lowercase()
+    );
+    if nullable.contains(grammar.start) {
+        let body = if only_empty(grammar.start) {
+            "blank()".to_string()
+        } else {
+            format!("optional($.{})", rule_name(grammar.start))
+        };
+        result.push_str(&format!("    start_: $ => {body},\n\n"));
+    }
+
+    for nt in ordered_nonterminals(grammar) {
+        if only_empty(nt) {
+            continue;
+        }
+
+        let mut choices = Vec::new();
+        for rule_index in grammar.rule_idxs_from_nt(nt) {
+            let rhs = &grammar.rules[rule_index as usize].rhs;
+            // Each symbol, and whether it may be left out.
+            let parts: Vec<(String, bool)> = rhs
+                .chars()
+                .filter(|&ch| !only_empty(ch))
+                .map(|ch| {
+                    if ch.is_uppercase() {
+                        (format!("$.{}", rule_name(ch)), nullable.contains(ch))
+                    } else {
+                        (quoted(ch), false)
+                    }
+                })
+                .collect();
+            let Some(body) = nonempty_body(&parts) else { continue };
+            let body = match grammar.rule_precedence(rule_index as usize) {
+                Some((level, Associativity::Left)) => format!("prec.left({}, {body})", level + 1),
+                Some((level, Associativity::Right)) => format!("prec.right({}, {body})", level + 1),
+                Some((level, Associativity::NonAssoc)) => format!("prec({}, {body})", level + 1),
+                None => body,
+            };
+            choices.push(body);
+        }
+
+        let body = if choices.len() == 1 {
+            choices.pop().unwrap()
+        } else {
+            let lines: Vec<String> = choices
+                .iter()
+                .map(|choice| format!("      {choice},\n"))
+                .collect();
+            format!("choice(\n{}    )", lines.concat())
+        };
+        result.push_str(&format!("    {}: $ => {body},\n\n", rule_name(nt)));
+    }
+
+    if result.ends_with(",\n\n") {
+        result.truncate(result.len() - 1);
+    }
+    result.push_str("  }\n});\n");
+    result
+}
+
+// A tree-sitter body for the nonempty words of a rhs, given as parts that
+// may or may not be left out. When every part may be, there is one
+// alternative per part that comes first. None for an empty rhs.
+fn nonempty_body(parts: &[(String, bool)]) -> Option<String> {
+    let wrap = |(part, optional): &(String, bool)| {
+        if *optional {
+            format!("optional({part})")
+        } else {
+            part.clone()
+        }
+    };
+    if parts.iter().any(|(_, optional)| !optional) {
+        return Some(sequence(parts.iter().map(wrap).collect()));
+    }
+
+    let mut firsts: Vec<String> = (0..parts.len())
+        .map(|first| {
+            let mut items = vec![parts[first].0.clone()];
+            items.extend(parts[first + 1..].iter().map(wrap));
+            sequence(items)
+        })
+        .collect();
+    match firsts.len() {
+        0 => None,
+        1 => firsts.pop(),
+        _ => Some(format!("choice({})", firsts.join(", "))),
+    }
+}
+
+fn sequence(mut items: Vec<String>) -> String {
+    if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        format!("seq({})", items.join(", "))
+    }
+}
+
+// Nonterminals that derive some word other than ε.
+fn nonempty_nonterminals(grammar: &Grammar) -> String {
+    let mut nonempty = String::new();
+
+    loop {
+        let mut changed = false;
+        for rule in &grammar.rules {
+            if !nonempty.contains(rule.lhs)
+                && rule
+                    .rhs
+                    .chars()
+                    .any(|ch| !ch.is_uppercase() || nonempty.contains(ch))
+            {
+                nonempty.push(rule.lhs);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    nonempty
+}
+
+// Nonterminals with rules, start symbol first.
+fn ordered_nonterminals(grammar: &Grammar) -> Vec<char> {
+    grammar
+        .nonterminals
+        .chars()
+        .filter(|&nt| nt == grammar.start || !grammar.rule_idxs_from_nt(nt).is_empty())
+        .collect()
+}
+
+fn alternatives(grammar: &Grammar, nonterminal: char) -> Vec<String> {
+    grammar
+        .rule_idxs_from_nt(nonterminal)
+        .into_iter()
+        .map(|i| grammar.rules[i as usize].rhs.clone())
+        .collect()
+}
+
+// Rhs symbols, with nonterminals as rule names and terminals through `terminal`.
+fn symbols(rhs: &str, terminal: impl Fn(char) -> String) -> Vec<String> {
+    rhs.chars()
+        .map(|ch| {
+            if ch.is_uppercase() {
+                rule_name(ch)
+            } else {
+                terminal(ch)
+            }
+        })
+        .collect()
+}
+
+// A single-quoted literal, valid in C, ANTLR and JavaScript alike.
+fn quoted(ch: char) -> String {
+    match ch {
+        '\'' => "'\\''".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        _ => format!("'{ch}'"),
+    }
+}
+
+// bison only accepts single-byte character literals, so other terminals
+// become named tokens.
+fn c_char(ch: char) -> String {
+    if ch.is_ascii() {
+        quoted(ch)
+    } else {
+        format!("T_{:04x}", ch as u32)
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(source: &str) -> Grammar {
+        Grammar::parse(source).unwrap()
+    }
+
+    #[test]
+    fn tree_sitter_makes_empty_rules_optional() {
+        // As imported from `list = item , { "," , item } ;`.
+        let list = grammar("L -> IA\nI -> a\nA -> ,IA\nA ->\n");
+        assert_eq!(
+            tree_sitter(&list, "List"),
+            "module.exports = grammar({
+  name: 'list',
+
+  rules: {
+    l: $ => seq($.i, optional($.a)),
+
+    i: $ => 'a',
+
+    a: $ => seq(',', $.i, optional($.a)),
+  }
+});
+"
+        );
+    }
+
+    #[test]
+    fn tree_sitter_nullable_start() {
+        let nullable = grammar("S -> AB\nS -> aSb\nA -> a\nA ->\nB -> b\nB -> E\nE ->\n");
+        let exported = tree_sitter(&nullable, "n");
+        // Only the first rule may match the empty string, and E, which
+        // derives nothing else, is left out.
+        assert!(exported.contains("    start_: $ => optional($.s),\n"));
+        assert!(exported.contains("      choice(seq($.a, optional($.b)), $.b),\n"));
+        assert!(exported.contains("      seq('a', optional($.s), 'b'),\n"));
+        assert!(exported.contains("    a: $ => 'a',\n"));
+        assert!(exported.contains("    b: $ => 'b',\n"));
+        assert!(!exported.contains("blank()"));
+        assert!(!exported.contains("$.e"));
+
+        let empty = tree_sitter(&grammar("S ->\n"), "empty");
+        assert!(empty.contains("    start_: $ => blank(),\n"));
+        assert!(!empty.contains("    s: $"));
+    }
+
+    #[test]
+    fn bison_keeps_empty_rules() {
+        let exported = bison(&grammar("S -> aS\nS ->\n"));
+        assert!(exported.contains("\ns\n    : 'a' s\n    | %empty\n    ;\n"));
+    }
+}
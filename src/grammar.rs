@@ -1,16 +1,43 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Rule {
     pub lhs: char,
     pub rhs: String,
 }
 
+/// Deserialized through `GrammarSource`, so the symbol sets of a JSON
+/// grammar are always rebuilt from its rules.
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "GrammarSource")]
 pub struct Grammar {
     pub start: char,
     pub rules: Vec<Rule>,
     pub terminals: String,
     pub nonterminals: String,
+    pub precedence: Vec<(Associativity, String)>, // Lowest precedence first
+}
+
+// The fields of a serialized grammar that are not derived from its rules.
+#[derive(Deserialize)]
+struct GrammarSource {
+    start: char,
+    rules: Vec<Rule>,
+    #[serde(default)]
+    precedence: Vec<(Associativity, String)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Associativity {
+    Left,
+    Right,
+    NonAssoc,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Sentential {
     pub form: String,
     pub first_nt_index: i32, // -1 if no non-terminal
@@ -18,6 +45,7 @@ pub struct Sentential {
 
 pub type DerivationStep = (i32, Sentential); // (rule_index, resulting_sentential)
 
+#[derive(Serialize, Deserialize)]
 pub struct Derivation {
     pub steps: Vec<DerivationStep>,
 }
@@ -42,10 +70,10 @@ impl Rule {
         let right: Vec<char> = self.rhs.chars().collect();
 
         for (i, char) in right.iter().enumerate() {
-            if char.is_uppercase() && i == 0 {
-                continue;
-            } else if char.is_lowercase() && i == 0 {
-                return false;
+            if i == 0 {
+                if char.is_lowercase() {
+                    return false;
+                }
             } else if char.is_uppercase() {
                 return false;
             }
@@ -57,12 +85,10 @@ impl Rule {
         let right: Vec<char> = self.rhs.chars().collect();
 
         for (i, char) in right.iter().enumerate() {
-            if right.len() == 1 && i == 0 && char.is_lowercase() {
+            if char.is_uppercase() {
+                return i == right.len() - 1;
+            } else if right.len() == 1 && char.is_lowercase() {
                 return true;
-            } else if char.is_uppercase() && i == right.len() - 1 {
-                return true;
-            } else if char.is_uppercase() {
-                return false;
             }
         }
         true
@@ -72,8 +98,13 @@ impl Rule {
 impl Grammar {
     pub fn from_rules(rules: Vec<Rule>) -> Grammar {
         let start = rules[0].lhs;
+        Grammar::with_start(start, rules)
+    }
+
+    /// Builds a grammar with an explicit start symbol, which may have no rules.
+    pub fn with_start(start: char, rules: Vec<Rule>) -> Grammar {
         let mut terminals = String::new();
-        let mut nonterminals = String::new();
+        let mut nonterminals = start.to_string();
 
         for rule in &rules {
             // Checks if non_terminal char is already in string
@@ -95,12 +126,96 @@ impl Grammar {
 
         Grammar {
             start,
-            rules: rules.clone(),
+            rules,
             terminals,
             nonterminals,
+            precedence: Vec::new(),
         }
     }
 
+    /// Parses the grammar file format: one `A -> rhs` rule per line, where the
+    /// first rule's lhs is the start symbol unless a `%start A` line says
+    /// otherwise. Whitespace inside a rhs is ignored and an empty rhs is an
+    /// epsilon rule. Lines starting with `#` are comments.
+    ///
+    /// `%left`, `%right` and `%nonassoc` lines list terminals sharing a
+    /// precedence level, as in yacc: later lines bind tighter.
+    pub fn parse(source: &str) -> Result<Grammar, GrammarError> {
+        Grammar::parse_with_lines(source).map(|(grammar, _)| grammar)
+    }
+
+    /// Like `parse`, but also returns the source line number of each rule.
+    pub fn parse_with_lines(source: &str) -> Result<(Grammar, Vec<usize>), GrammarError> {
+        let mut start = None;
+        let mut rules = Vec::new();
+        let mut lines = Vec::new();
+        let mut precedence = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(declaration) = line.strip_prefix('%') {
+                let (keyword, rest) = declaration
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((declaration, ""));
+                let associativity = match keyword {
+                    "start" => {
+                        start = Some(parse_nonterminal(rest.trim(), line_number)?);
+                        continue;
+                    }
+                    "left" => Associativity::Left,
+                    "right" => Associativity::Right,
+                    "nonassoc" => Associativity::NonAssoc,
+                    _ => return Err(GrammarError::UnknownDeclaration { line: line_number }),
+                };
+
+                let terminals: String = rest.chars().filter(|ch| !ch.is_whitespace()).collect();
+                if terminals.is_empty() || terminals.chars().any(char::is_uppercase) {
+                    return Err(GrammarError::InvalidPrecedence { line: line_number });
+                }
+                precedence.push((associativity, terminals));
+                continue;
+            }
+
+            let (lhs, rhs) = line
+                .split_once("->")
+                .ok_or(GrammarError::MissingArrow { line: line_number })?;
+            let lhs = parse_nonterminal(lhs.trim(), line_number)?;
+            let rhs: String = rhs.chars().filter(|ch| !ch.is_whitespace()).collect();
+            rules.push(Rule { lhs, rhs });
+            lines.push(line_number);
+        }
+
+        let mut grammar = match (start, rules.first()) {
+            (Some(start), _) => Grammar::with_start(start, rules),
+            (None, Some(first)) => Grammar::with_start(first.lhs, rules),
+            (None, None) => return Err(GrammarError::NoRules),
+        };
+        grammar.precedence = precedence;
+        Ok((grammar, lines))
+    }
+
+    /// Writes the grammar back out in the format read by `Grammar::parse`.
+    pub fn to_source(&self) -> String {
+        let mut result = String::new();
+        if self.rules.first().map(|rule| rule.lhs) != Some(self.start) {
+            result.push_str(&format!("%start {}\n", self.start));
+        }
+        for (associativity, terminals) in &self.precedence {
+            let terminals: Vec<String> = terminals.chars().map(|ch| ch.to_string()).collect();
+            result.push_str(&format!("%{} {}\n", associativity.keyword(), terminals.join(" ")));
+        }
+        for rule in &self.rules {
+            result.push_str(&format!("{}\n", rule.display()));
+        }
+        result
+    }
+
     pub fn display(&self) -> String {
         let mut result = "Grammer:\n".to_string();
         for rule in &self.rules {
@@ -128,24 +243,585 @@ impl Grammar {
         true
     }
 
+    /// Nonterminals that can derive a word made only of terminals.
+    pub fn generating_nonterminals(&self) -> String {
+        let mut generating = String::new();
+
+        loop {
+            let mut changed = false;
+            for rule in &self.rules {
+                if !generating.contains(rule.lhs)
+                    && rule
+                        .rhs
+                        .chars()
+                        .all(|ch| !ch.is_uppercase() || generating.contains(ch))
+                {
+                    generating.push(rule.lhs);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        generating
+    }
+
+    /// Nonterminals that can derive the empty word.
+    pub fn nullable_nonterminals(&self) -> String {
+        let mut nullable = String::new();
+
+        loop {
+            let mut changed = false;
+            for rule in &self.rules {
+                if !nullable.contains(rule.lhs) && rule.rhs.chars().all(|ch| nullable.contains(ch)) {
+                    nullable.push(rule.lhs);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        nullable
+    }
+
+    /// Nonterminals that appear in some sentential form derived from the start symbol.
+    pub fn reachable_nonterminals(&self) -> String {
+        let mut reachable = self.start.to_string();
+        let mut pending = vec![self.start];
+
+        while let Some(nt) = pending.pop() {
+            for rule in self.rules.iter().filter(|rule| rule.lhs == nt) {
+                for ch in rule.rhs.chars() {
+                    if ch.is_uppercase() && !reachable.contains(ch) {
+                        reachable.push(ch);
+                        pending.push(ch);
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Removes useless rules: first those using a nonterminal that cannot
+    /// generate a word, then those unreachable from the start symbol.
+    pub fn reduce(&self) -> Grammar {
+        let generating = self.generating_nonterminals();
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                generating.contains(rule.lhs)
+                    && rule
+                        .rhs
+                        .chars()
+                        .all(|ch| !ch.is_uppercase() || generating.contains(ch))
+            })
+            .cloned()
+            .collect();
+        let productive = Grammar::with_start(self.start, rules);
+
+        let reachable = productive.reachable_nonterminals();
+        let rules = productive
+            .rules
+            .into_iter()
+            .filter(|rule| reachable.contains(rule.lhs))
+            .collect();
+        self.inherit_precedence(Grammar::with_start(self.start, rules))
+    }
+
+    /// True when the start symbol cannot derive any word.
+    pub fn is_empty(&self) -> bool {
+        !self.generating_nonterminals().contains(self.start)
+    }
+
+    /// True when the grammar generates finitely many words. After removing
+    /// useless rules, the language is infinite exactly when some nonterminal
+    /// can derive itself with a non-empty word beside it.
+    pub fn is_finite(&self) -> bool {
+        let reduced = self.reduce();
+
+        // Nonterminals that can derive at least one non-empty word.
+        let mut solid = String::new();
+        loop {
+            let mut changed = false;
+            for rule in &reduced.rules {
+                if !solid.contains(rule.lhs)
+                    && rule
+                        .rhs
+                        .chars()
+                        .any(|ch| !ch.is_uppercase() || solid.contains(ch))
+                {
+                    solid.push(rule.lhs);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        for rule in &reduced.rules {
+            for (i, nt) in rule.rhs.chars().enumerate() {
+                if !nt.is_uppercase() {
+                    continue;
+                }
+                let grows = rule
+                    .rhs
+                    .chars()
+                    .enumerate()
+                    .any(|(j, ch)| j != i && (!ch.is_uppercase() || solid.contains(ch)));
+                if grows && reduced.depends_on(nt, rule.lhs) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Every word of the language, shortest first, or `None` when the
+    /// language is infinite.
+    pub fn finite_language(&self) -> Option<Vec<String>> {
+        if !self.is_finite() {
+            return None;
+        }
+
+        let mut words: Vec<String> = self.reduce().language_up_to(None).into_iter().collect();
+        words.sort_by_key(|word| word.chars().count());
+        Some(words)
+    }
+
+    // Words derivable from the start symbol, up to `max_len` characters.
+    // Without a length limit this only terminates for finite languages.
+    pub(crate) fn language_up_to(&self, max_len: Option<usize>) -> BTreeSet<String> {
+        let max_len = max_len.unwrap_or_else(|| self.longest_word());
+        self.words_by_length(max_len, usize::MAX)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    // Words derivable from the start symbol grouped by length, from 0 up to
+    // `max_len`. Lengths are built in order from the shorter ones, so no word
+    // longer than needed is ever formed. Once `work_limit` words have been
+    // formed the current length is dropped, so the result may stop before
+    // `max_len`.
+    pub(crate) fn words_by_length(
+        &self,
+        max_len: usize,
+        work_limit: usize,
+    ) -> Vec<BTreeSet<String>> {
+        let mut by_length: Vec<HashMap<char, BTreeSet<String>>> = Vec::new();
+        let mut budget = work_limit;
+
+        'lengths: for length in 0..=max_len {
+            by_length.push(HashMap::new());
+            // Unit and epsilon rules make words of a length depend on other
+            // words of the same length, so repeat until nothing new is found.
+            loop {
+                let mut changed = false;
+                for rule in &self.rules {
+                    let symbols: Vec<char> = rule.rhs.chars().collect();
+                    let mut words = BTreeSet::new();
+                    combine(&by_length, &symbols, length, String::new(), &mut words, &mut budget);
+                    if budget == 0 {
+                        by_length.pop();
+                        break 'lengths;
+                    }
+
+                    let language = by_length[length].entry(rule.lhs).or_default();
+                    for word in words {
+                        changed |= language.insert(word);
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+        }
+
+        by_length
+            .into_iter()
+            .map(|mut languages| languages.remove(&self.start).unwrap_or_default())
+            .collect()
+    }
+
+    // Length of the longest word, for a finite language.
+    fn longest_word(&self) -> usize {
+        let mut longest: HashMap<char, usize> = HashMap::new();
+
+        loop {
+            let mut changed = false;
+            for rule in &self.rules {
+                let length: Option<usize> = rule
+                    .rhs
+                    .chars()
+                    .map(|ch| match ch.is_uppercase() {
+                        true => longest.get(&ch).copied(),
+                        false => Some(1),
+                    })
+                    .sum();
+                if let Some(length) = length
+                    && longest.get(&rule.lhs).is_none_or(|&known| known < length)
+                {
+                    longest.insert(rule.lhs, length);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        longest.get(&self.start).copied().unwrap_or(0)
+    }
+
+    // Whether `to` appears in some sentential form derived from `from`.
+    fn depends_on(&self, from: char, to: char) -> bool {
+        let mut seen = from.to_string();
+        let mut pending = vec![from];
+
+        while let Some(nt) = pending.pop() {
+            if nt == to {
+                return true;
+            }
+            for rule in self.rules.iter().filter(|rule| rule.lhs == nt) {
+                for ch in rule.rhs.chars() {
+                    if ch.is_uppercase() && !seen.contains(ch) {
+                        seen.push(ch);
+                        pending.push(ch);
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Precedence level of a terminal, counting from 0 for the lowest.
+    pub fn terminal_precedence(&self, terminal: char) -> Option<(usize, Associativity)> {
+        self.precedence
+            .iter()
+            .position(|(_, terminals)| terminals.contains(terminal))
+            .map(|level| (level, self.precedence[level].0))
+    }
+
+    /// Precedence of a rule, taken from the last terminal in its rhs like yacc.
+    pub fn rule_precedence(&self, rule_index: usize) -> Option<(usize, Associativity)> {
+        self.rules[rule_index]
+            .rhs
+            .chars()
+            .rfind(|ch| !ch.is_uppercase())
+            .and_then(|terminal| self.terminal_precedence(terminal))
+    }
+
     pub fn rule_idxs_from_nt(&self, nonterminal: char) -> Vec<i32> {
         let mut indices = Vec::new();
-        let mut count = 0;
 
-        for rule in &self.rules {
+        for (count, rule) in self.rules.iter().enumerate() {
             if rule.lhs == nonterminal {
-                indices.push(count);
+                indices.push(count as i32);
             }
-            count += 1;
         }
 
         indices
     }
 }
 
+impl Grammar {
+    /// Grammar for L(self) | L(other).
+    pub fn union(&self, other: &Grammar) -> Result<Grammar, OutOfNonterminals> {
+        let other = other.disjoint_from(self)?;
+        let start = fresh_nonterminal(&format!("{}{}", self.nonterminals, other.nonterminals))
+            .ok_or(OutOfNonterminals)?;
+
+        let mut rules = vec![
+            Rule::new(start, &self.start.to_string()),
+            Rule::new(start, &other.start.to_string()),
+        ];
+        rules.extend(self.rules.iter().cloned());
+        rules.extend(other.rules);
+        Ok(Grammar::with_start(start, rules))
+    }
+
+    /// Grammar for L(self) followed by L(other).
+    pub fn concatenation(&self, other: &Grammar) -> Result<Grammar, OutOfNonterminals> {
+        let other = other.disjoint_from(self)?;
+        let start = fresh_nonterminal(&format!("{}{}", self.nonterminals, other.nonterminals))
+            .ok_or(OutOfNonterminals)?;
+
+        let mut rules = vec![Rule::new(start, &format!("{}{}", self.start, other.start))];
+        rules.extend(self.rules.iter().cloned());
+        rules.extend(other.rules);
+        Ok(Grammar::with_start(start, rules))
+    }
+
+    /// Grammar for the Kleene star of L(self).
+    pub fn star(&self) -> Result<Grammar, OutOfNonterminals> {
+        let start = fresh_nonterminal(&self.nonterminals).ok_or(OutOfNonterminals)?;
+
+        let mut rules = vec![
+            Rule::new(start, &format!("{}{}", self.start, start)),
+            Rule::new(start, ""),
+        ];
+        rules.extend(self.rules.iter().cloned());
+        Ok(self.inherit_precedence(Grammar::with_start(start, rules)))
+    }
+
+    /// Grammar for the words of L(self) written backwards.
+    pub fn reversal(&self) -> Grammar {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| Rule {
+                lhs: rule.lhs,
+                rhs: rule.rhs.chars().rev().collect(),
+            })
+            .collect();
+        self.inherit_precedence(Grammar::with_start(self.start, rules))
+    }
+
+    /// Returns a copy of the grammar with every nonterminal replaced through `map`.
+    /// Nonterminals missing from the map are kept as they are.
+    pub fn rename(&self, map: &HashMap<char, char>) -> Grammar {
+        let rename = |ch: char| *map.get(&ch).unwrap_or(&ch);
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| Rule {
+                lhs: rename(rule.lhs),
+                rhs: rule.rhs.chars().map(rename).collect(),
+            })
+            .collect();
+        self.inherit_precedence(Grammar::with_start(rename(self.start), rules))
+    }
+
+    // Carries the precedence declarations over to a grammar built from this one.
+    fn inherit_precedence(&self, mut grammar: Grammar) -> Grammar {
+        grammar.precedence = self.precedence.clone();
+        grammar
+    }
+
+    // Renames this grammar's nonterminals so none of them appear in `other`.
+    fn disjoint_from(&self, other: &Grammar) -> Result<Grammar, OutOfNonterminals> {
+        let mut used = format!("{}{}", other.nonterminals, self.nonterminals);
+        let mut map = HashMap::new();
+
+        for nt in self.nonterminals.chars() {
+            if other.nonterminals.contains(nt) {
+                let fresh = fresh_nonterminal(&used).ok_or(OutOfNonterminals)?;
+                used.push(fresh);
+                map.insert(nt, fresh);
+            }
+        }
+
+        Ok(self.rename(&map))
+    }
+}
+
+// Adds to `words` every `prefix` followed by a word of exactly `remaining`
+// characters matching `symbols`. Each word formed uses up one unit of
+// `budget`, and nothing more is formed once it runs out.
+fn combine(
+    by_length: &[HashMap<char, BTreeSet<String>>],
+    symbols: &[char],
+    remaining: usize,
+    prefix: String,
+    words: &mut BTreeSet<String>,
+    budget: &mut usize,
+) {
+    let Some((&symbol, rest)) = symbols.split_first() else {
+        if remaining == 0 {
+            words.insert(prefix);
+        }
+        return;
+    };
+    // Terminals further on need one character each.
+    let rest_minimum = rest.iter().filter(|ch| !ch.is_uppercase()).count();
+    if remaining < rest_minimum + usize::from(!symbol.is_uppercase()) {
+        return;
+    }
+
+    if !symbol.is_uppercase() {
+        if *budget == 0 {
+            return;
+        }
+        *budget -= 1;
+        combine(by_length, rest, remaining - 1, format!("{prefix}{symbol}"), words, budget);
+        return;
+    }
+    for length in 0..=remaining - rest_minimum {
+        let Some(language) = by_length.get(length).and_then(|languages| languages.get(&symbol))
+        else {
+            continue;
+        };
+        for word in language {
+            if *budget == 0 {
+                return;
+            }
+            *budget -= 1;
+            combine(by_length, rest, remaining - length, format!("{prefix}{word}"), words, budget);
+        }
+    }
+}
+
+/// Picks an uppercase character that does not appear in `used`. Runs through
+/// `A..Z` first and then the rest of the uppercase Unicode letters, since
+/// nonterminals are single characters. None once every one of them is used.
+pub fn fresh_nonterminal(used: &str) -> Option<char> {
+    ('A'..='Z')
+        .chain('\u{C0}'..=char::MAX)
+        .find(|ch| ch.is_uppercase() && !used.contains(*ch))
+}
+
+impl Associativity {
+    /// Name of the grammar file declaration, without the `%`.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Associativity::Left => "left",
+            Associativity::Right => "right",
+            Associativity::NonAssoc => "nonassoc",
+        }
+    }
+}
+
+fn parse_nonterminal(symbol: &str, line: usize) -> Result<char, GrammarError> {
+    let mut chars = symbol.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if ch.is_uppercase() => Ok(ch),
+        _ => Err(GrammarError::InvalidNonterminal { line }),
+    }
+}
+
+#[derive(Debug)]
+pub enum GrammarError {
+    NoRules,
+    MissingArrow { line: usize },
+    InvalidNonterminal { line: usize },
+    UnknownDeclaration { line: usize },
+    InvalidPrecedence { line: usize },
+    NotNonterminal { symbol: char }, // Start or lhs of a JSON grammar
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrammarError::NoRules => write!(f, "grammar has no rules"),
+            GrammarError::MissingArrow { line } => write!(f, "line {line}: expected `A -> rhs`"),
+            GrammarError::InvalidNonterminal { line } => {
+                write!(f, "line {line}: nonterminal must be a single uppercase character")
+            }
+            GrammarError::UnknownDeclaration { line } => {
+                write!(f, "line {line}: unknown declaration")
+            }
+            GrammarError::InvalidPrecedence { line } => {
+                write!(f, "line {line}: precedence declarations list one or more terminals")
+            }
+            GrammarError::NotNonterminal { symbol } => {
+                write!(f, "{symbol:?} is used as a nonterminal but is not uppercase")
+            }
+        }
+    }
+}
+
+impl Error for GrammarError {}
+
+impl TryFrom<GrammarSource> for Grammar {
+    type Error = GrammarError;
+
+    fn try_from(source: GrammarSource) -> Result<Grammar, GrammarError> {
+        if source.rules.is_empty() {
+            return Err(GrammarError::NoRules);
+        }
+        let lhs = source.rules.iter().map(|rule| rule.lhs);
+        if let Some(symbol) = std::iter::once(source.start)
+            .chain(lhs)
+            .find(|ch| !ch.is_uppercase())
+        {
+            return Err(GrammarError::NotNonterminal { symbol });
+        }
+
+        let mut grammar = Grammar::with_start(source.start, source.rules);
+        grammar.precedence = source.precedence;
+        Ok(grammar)
+    }
+}
+
+impl fmt::Display for DerivationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DerivationError::NoNonTerminal => {
+                write!(f, "the sentential form has no nonterminal left")
+            }
+            DerivationError::RuleOutOfRange {
+                rule_index,
+                rule_count,
+            } => write!(f, "rule {rule_index} does not exist, the grammar has {rule_count} rules"),
+            DerivationError::InvalidRule {
+                rule_index,
+                expected,
+                found,
+            } => write!(f, "rule {rule_index} rewrites {found}, not {expected}"),
+            DerivationError::NoRules { nonterminal } => write!(f, "no rule rewrites {nonterminal}"),
+            DerivationError::StepLimitExceeded { limit } => {
+                write!(f, "no word within {limit} steps")
+            }
+        }
+    }
+}
+
+impl Error for DerivationError {}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {}: {}", self.step, self.error)?;
+        if !self.valid_rules.is_empty() {
+            let valid: Vec<String> = self.valid_rules.iter().map(|i| i.to_string()).collect();
+            write!(f, "; valid rules: {}", valid.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for OutOfNonterminals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ran out of uppercase characters for nonterminals")
+    }
+}
+
+impl Error for OutOfNonterminals {}
+
+#[derive(Debug)]
 pub enum DerivationError {
     NoNonTerminal,
-    InvalidRule,
+    RuleOutOfRange { rule_index: usize, rule_count: usize },
+    InvalidRule { rule_index: usize, expected: char, found: char }, // found is the rule's lhs
+    NoRules { nonterminal: char },
+    StepLimitExceeded { limit: u32 },
+}
+
+/// A construction needed more new nonterminals than there are uppercase
+/// characters left.
+#[derive(Debug)]
+pub struct OutOfNonterminals;
+
+/// The first rule of a replayed sequence that could not be applied.
+#[derive(Debug)]
+pub struct ReplayError {
+    pub step: usize, // Numbered like get_history, starting at 1
+    pub error: DerivationError,
+    pub valid_rules: Vec<i32>, // Rules for the leftmost nonterminal, if any
 }
 
 impl Sentential {
@@ -165,16 +841,55 @@ impl Sentential {
             return Err(DerivationError::NoNonTerminal);
         }
 
-        let rule = &grammar.rules[rule_index];
-        // Gets character at first_nt_index and compares to lhs of rule
-        if rule.lhs != self.form.chars().nth(self.first_nt_index as usize).unwrap() {
-            return Err(DerivationError::InvalidRule);
+        self.expand(grammar, rule_index, self.first_nt_index as usize)
+    }
+
+    /// Like `new_next`, but rewrites the rightmost nonterminal.
+    pub fn new_next_rightmost(
+        &self,
+        grammar: &Grammar,
+        rule_index: usize,
+    ) -> Result<Sentential, DerivationError> {
+        let last_nt_index = self
+            .form
+            .chars()
+            .enumerate()
+            .filter(|(_, ch)| ch.is_uppercase())
+            .last()
+            .map(|(i, _)| i)
+            .ok_or(DerivationError::NoNonTerminal)?;
+
+        self.expand(grammar, rule_index, last_nt_index)
+    }
+
+    // Replaces the nonterminal at `nt_index` with the rhs of the rule.
+    fn expand(
+        &self,
+        grammar: &Grammar,
+        rule_index: usize,
+        nt_index: usize,
+    ) -> Result<Sentential, DerivationError> {
+        let rule = grammar
+            .rules
+            .get(rule_index)
+            .ok_or(DerivationError::RuleOutOfRange {
+                rule_index,
+                rule_count: grammar.rules.len(),
+            })?;
+        // Gets character at nt_index and compares to lhs of rule
+        let expected = self.form.chars().nth(nt_index).unwrap();
+        if rule.lhs != expected {
+            return Err(DerivationError::InvalidRule {
+                rule_index,
+                expected,
+                found: rule.lhs,
+            });
         }
 
         let mut new_form = String::new();
 
         for (i, ch) in self.form.chars().enumerate() {
-            if i as i32 == self.first_nt_index {
+            if i == nt_index {
                 new_form.push_str(&rule.rhs);
             } else {
                 new_form.push(ch);
@@ -233,6 +948,21 @@ impl Derivation {
         Ok(())
     }
 
+    pub fn derive_rightmost(
+        &mut self,
+        grammar: &Grammar,
+        rule_index: usize,
+    ) -> Result<(), DerivationError> {
+        let next_step = self
+            .steps
+            .last()
+            .unwrap()
+            .1
+            .new_next_rightmost(grammar, rule_index)?;
+        self.steps.push((rule_index as i32, next_step));
+        Ok(())
+    }
+
     pub fn is_complete(&self) -> bool {
         self.steps.last().unwrap().1.is_complete()
     }
@@ -256,39 +986,60 @@ impl Derivation {
         self.steps.last().unwrap().1.form.clone()
     }
 
+    /// Removes the last step and returns its rule index. The start form is
+    /// never removed.
+    pub fn undo(&mut self) -> Option<usize> {
+        if self.steps.len() == 1 {
+            return None;
+        }
+        self.steps.pop().map(|(rule_index, _)| rule_index as usize)
+    }
+
+    /// Applies rule indices in order with `derive_leftmost`. Stops at the first
+    /// rule that does not apply, leaving the steps taken so far in place.
+    pub fn replay(&mut self, grammar: &Grammar, rule_indices: &[usize]) -> Result<(), ReplayError> {
+        for &rule_index in rule_indices {
+            if let Err(error) = self.derive_leftmost(grammar, rule_index) {
+                return Err(ReplayError {
+                    step: self.steps.len(),
+                    error,
+                    valid_rules: self
+                        .leftmost_nonterminal()
+                        .map(|nt| grammar.rule_idxs_from_nt(nt))
+                        .unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn print_random(
         &mut self,
         grammar: &Grammar,
         step_limit: Option<u32>
-    ) -> Option<String>{
+    ) -> Result<String, DerivationError>{
         use rand::prelude::*;
 
         let mut rng = rand::rng();
-        let mut current_rule_index: usize;
 
         let mut step_count = 0;
 
-        loop {
-            current_rule_index = rng.random_range(0..grammar.rules.len());
-
-            let _ = self.derive_leftmost(grammar, current_rule_index);
-
-            step_count += 1;
-
-            if step_count == step_limit.unwrap_or(0) {
-                break;
-            }
-
-            if self.is_complete(){
-                break;
+        // Only rules for the leftmost nonterminal apply, so pick among those.
+        while let Some(nt) = self.leftmost_nonterminal() {
+            if step_limit == Some(step_count) {
+                return Err(DerivationError::StepLimitExceeded { limit: step_count });
             }
 
-        }
+            let rule_index = *grammar
+                .rule_idxs_from_nt(nt)
+                .choose(&mut rng)
+                .ok_or(DerivationError::NoRules { nonterminal: nt })?;
+            self.derive_leftmost(grammar, rule_index as usize)?;
 
-        if self.is_complete() {
-            return Some(self.word());
+            step_count += 1;
         }
 
-        None
+        Ok(self.word())
     }
 }
@@ -0,0 +1,239 @@
+use serde::Serialize;
+
+use crate::grammar::Grammar;
+
+/// Which nonterminals each nonterminal's rules use. There is one edge per
+/// (lhs, rhs nonterminal) pair, listing the rules that contain it.
+#[derive(Serialize)]
+pub struct DependencyGraph {
+    pub start: char,
+    pub nodes: Vec<char>,
+    pub edges: Vec<Edge>,
+    pub components: Vec<Vec<char>>, // Recursive strongly connected components
+}
+
+#[derive(Serialize)]
+pub struct Edge {
+    pub from: char,
+    pub to: char,
+    pub rules: Vec<usize>,
+}
+
+// Tarjan's algorithm state, indexed like DependencyGraph::nodes.
+struct Tarjan<'a> {
+    successors: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    pub fn new(grammar: &Grammar) -> DependencyGraph {
+        let nodes: Vec<char> = grammar.nonterminals.chars().collect();
+        let position = |nt: char| nodes.iter().position(|&node| node == nt).unwrap();
+
+        let mut edges: Vec<Edge> = Vec::new();
+        for (i, rule) in grammar.rules.iter().enumerate() {
+            for to in rule.rhs.chars().filter(|ch| ch.is_uppercase()) {
+                match edges
+                    .iter_mut()
+                    .find(|edge| edge.from == rule.lhs && edge.to == to)
+                {
+                    Some(edge) => {
+                        if edge.rules.last() != Some(&i) {
+                            edge.rules.push(i);
+                        }
+                    }
+                    None => edges.push(Edge {
+                        from: rule.lhs,
+                        to,
+                        rules: vec![i],
+                    }),
+                }
+            }
+        }
+        edges.sort_by_key(|edge| (position(edge.from), position(edge.to)));
+
+        let mut successors = vec![Vec::new(); nodes.len()];
+        for edge in &edges {
+            successors[position(edge.from)].push(position(edge.to));
+        }
+
+        // A component is recursive if it has a cycle: more than one node, or
+        // a node that uses itself.
+        let mut components: Vec<Vec<usize>> = Tarjan::components(&successors)
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || successors[component[0]].contains(&component[0])
+            })
+            .collect();
+        for component in &mut components {
+            component.sort();
+        }
+        components.sort();
+        let components = components
+            .into_iter()
+            .map(|component| component.into_iter().map(|i| nodes[i]).collect())
+            .collect();
+
+        DependencyGraph {
+            start: grammar.start,
+            nodes,
+            edges,
+            components,
+        }
+    }
+
+    /// Index into `components` of the recursive component containing `nt`.
+    pub fn component_of(&self, nt: char) -> Option<usize> {
+        self.components
+            .iter()
+            .position(|component| component.contains(&nt))
+    }
+
+    /// Graphviz source. Each recursive component is drawn as a filled cluster
+    /// and the start symbol as a double circle.
+    pub fn dot(&self) -> String {
+        let mut result = String::from("digraph grammar {\n    node [shape=circle];\n");
+        result.push_str(&format!(
+            "    {} [shape=doublecircle];\n",
+            dot_id(self.start)
+        ));
+
+        for (i, component) in self.components.iter().enumerate() {
+            result.push_str(&format!(
+                "\n    subgraph cluster_{i} {{\n        label=\"recursive {}\";\n        style=filled;\n        color=lightgrey;\n",
+                i + 1
+            ));
+            for &nt in component {
+                result.push_str(&format!("        {};\n", dot_id(nt)));
+            }
+            result.push_str("    }\n");
+        }
+
+        result.push('\n');
+        for &nt in &self.nodes {
+            if self.component_of(nt).is_none() {
+                result.push_str(&format!("    {};\n", dot_id(nt)));
+            }
+        }
+        for edge in &self.edges {
+            let rules: Vec<String> = edge.rules.iter().map(|i| i.to_string()).collect();
+            let style = match (self.component_of(edge.from), self.component_of(edge.to)) {
+                (Some(a), Some(b)) if a == b => ", color=red",
+                _ => "",
+            };
+            result.push_str(&format!(
+                "    {} -> {} [label=\"{}\"{style}];\n",
+                dot_id(edge.from),
+                dot_id(edge.to),
+                rules.join(", ")
+            ));
+        }
+
+        result.push_str("}\n");
+        result
+    }
+
+    /// Adjacency list with the rule indices of each edge, followed by the
+    /// recursive components.
+    pub fn display(&self) -> String {
+        let mut result = String::new();
+        for &nt in &self.nodes {
+            let targets: Vec<String> = self
+                .edges
+                .iter()
+                .filter(|edge| edge.from == nt)
+                .map(|edge| {
+                    let rules: Vec<String> = edge.rules.iter().map(|i| i.to_string()).collect();
+                    format!("{} ({})", edge.to, rules.join(", "))
+                })
+                .collect();
+            let marker = match self.component_of(nt) {
+                Some(i) => format!(" [recursive {}]", i + 1),
+                None => String::new(),
+            };
+            if targets.is_empty() {
+                result.push_str(&format!("{nt}{marker}\n"));
+            } else {
+                result.push_str(&format!("{nt}{marker} -> {}\n", targets.join(", ")));
+            }
+        }
+
+        if self.components.is_empty() {
+            result.push_str("\nNo recursion.\n");
+        } else {
+            result.push_str("\nRecursive components:\n");
+            for (i, component) in self.components.iter().enumerate() {
+                let nts: Vec<String> = component.iter().map(|nt| nt.to_string()).collect();
+                result.push_str(&format!("  {}: {{{}}}\n", i + 1, nts.join(", ")));
+            }
+        }
+        result
+    }
+}
+
+impl Tarjan<'_> {
+    // Strongly connected components in reverse topological order.
+    fn components(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let count = successors.len();
+        let mut tarjan = Tarjan {
+            successors,
+            index: vec![None; count],
+            low_link: vec![0; count],
+            on_stack: vec![false; count],
+            stack: Vec::new(),
+            next_index: 0,
+            components: Vec::new(),
+        };
+
+        for node in 0..count {
+            if tarjan.index[node].is_none() {
+                tarjan.visit(node);
+            }
+        }
+        tarjan.components
+    }
+
+    fn visit(&mut self, node: usize) {
+        self.index[node] = Some(self.next_index);
+        self.low_link[node] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for &next in &self.successors[node] {
+            match self.index[next] {
+                None => {
+                    self.visit(next);
+                    self.low_link[node] = self.low_link[node].min(self.low_link[next]);
+                }
+                Some(index) if self.on_stack[next] => {
+                    self.low_link[node] = self.low_link[node].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.low_link[node]) == self.index[node] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+// Quoted so any uppercase character is a valid DOT id.
+fn dot_id(nt: char) -> String {
+    format!("\"{nt}\"")
+}
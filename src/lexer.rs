@@ -1,8 +1,12 @@
 #![allow(warnings)]
 
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
 use strum_macros::EnumIter;
 
-#[derive(Debug, EnumIter)]
+#[derive(Debug, Clone, EnumIter, Serialize, Deserialize)]
 pub enum Token {
     // Brackets
     PARENS_L,
@@ -49,6 +53,10 @@ pub enum Token {
     IF,
     ELSE,
     WHILE,
+    FOR,
+    BREAK,
+    CONTINUE,
+    RETURN,
     PRINT,
 
     // Identifiers
@@ -56,448 +64,927 @@ pub enum Token {
 
     // Basic Types
     TYPE_INT32,
+    TYPE_INT64,
+    TYPE_UINT32,
+    TYPE_UINT64,
     TYPE_FLT32,
+    TYPE_FLT64,
     TYPE_CHAR,
+    TYPE_BOOL,
 
     // Literals
     LIT_INT32 { value: i32 },
+    LIT_INT64 { value: i64 },
+    LIT_UINT32 { value: u32 },
+    LIT_UINT64 { value: u64 },
     LIT_FLT32 { value: f32 },
+    LIT_FLT64 { value: f64 },
     LIT_CHAR { value: char },
+    LIT_BOOL { value: bool },
     LIT_STRING { value: String },
 
+    // Comments and Whitespace
+    DOC_COMMENT { text: String }, // Text after `///`
+    COMMENT { text: String },     // Only in trivia mode
+    WHITESPACE { text: String },  // Only in trivia mode
+
     // End-of-Input
     EOI,
 }
 
-pub enum LexerState {
-    Start,
-    End,
+/// Where a token came from. `start..end` are byte offsets into the input;
+/// `line` and `column` count from 1, with columns in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
 
-    Chars,
-    ReadChar,
-    ReadString,
-    Numbers,
-    NumPoint,
-    Decimals,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
 
-    Not,
-    And,
-    Or,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LexErrorKind {
+    UnknownChar { ch: char },
+    LoneOperator { ch: char }, // `&` or `|` not doubled
+    MalformedChar { literal: String },
+    UnterminatedChar,
+    UnterminatedString,
+    UnterminatedComment,
+    UnknownEscape { escape: String },
+    InvalidUnicodeEscape { escape: String },
+    MissingRawQuote, // `r#` not followed by `"`
+    MissingDigits { literal: String },       // `0x` or `1e` without digits
+    InvalidDigit { literal: String, radix: u32 },
+    InvalidSuffix { literal: String, suffix: String },
+    NumberOverflow { literal: String, ty: String },
+}
 
-    Dash,
-    Slash,
+/// A lexical error and the span of the input it covers. The lexer skips
+/// that input, so lexing can continue after an error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+// Where the lexer is in the input: a byte offset, always on a character
+// boundary, and the line and column it falls on.
+#[derive(Debug, Clone, Copy)]
+struct Cursor {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
 
-    Equal,
-    Greater,
-    Less,
+/// A saved lexer position to `rewind` to, along with the token that was
+/// current there.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    cursor: Cursor,
+    done: bool,
+    token: Token,
+    span: Span,
 }
 
+/// Iterating yields each token or error in turn, ending after EOI.
 pub struct Lexer {
     input_string: String,
-    position: usize,
-    state: LexerState,
+    cursor: Cursor,
     pub current_token: Token,
-    buffer_string: String,
+    pub current_span: Span,
+    lookahead: VecDeque<(Cursor, Result<SpannedToken, LexError>)>, // Cursor before each
+    done: bool,   // EOI has been returned
+    trivia: bool, // Emit COMMENT and WHITESPACE tokens
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
         Lexer {
             input_string: input,
-            position: 0,
-            state: LexerState::Start,
+            cursor: Cursor {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
             current_token: Token::EOI,
-            buffer_string: String::new(),
+            current_span: Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+            lookahead: VecDeque::new(),
+            done: false,
+            trivia: false,
         }
     }
 
-    pub fn set_input(&mut self, input: String) {
-        self.input_string = input;
-        self.position = 0;
-        self.state = LexerState::Start;
-        self.current_token = Token::EOI;
-        self.buffer_string = String::new();
+    /// A lexer that also emits comments and whitespace as tokens, so the
+    /// tokens cover the whole input, as a formatter needs.
+    pub fn with_trivia(input: String) -> Self {
+        Lexer {
+            trivia: true,
+            ..Lexer::new(input)
+        }
     }
 
-    pub fn advance(&mut self) -> &Token {
-        loop {
-            if self.position == self.input_string.len() {
-                match self.state {
-                    LexerState::Greater => self.current_token = Token::GT,
-                    LexerState::Less => self.current_token = Token::LT,
-                    LexerState::Equal => self.current_token = Token::ASSIGN,
-                    LexerState::Not => self.current_token = Token::NOT,
-                    LexerState::Dash => self.current_token = Token::SUB,
-                    LexerState::Slash => self.current_token = Token::DIV,
-                    LexerState::And => self.current_token = Token::AND,
-                    LexerState::Or => self.current_token = Token::OR,
-                    LexerState::NumPoint => {
-                        let value: i32 = self.buffer_string.parse().unwrap();
-                        self.state = LexerState::Start;
-                        self.current_token = Token::LIT_INT32 { value };
-                        self.buffer_string = String::new();
-                        self.position -= 1;
-                        break;
-                    }
-                    _ => self.current_token = Token::EOI,
-                }
+    pub fn set_input(&mut self, input: String) {
+        let trivia = self.trivia;
+        *self = Lexer::new(input);
+        self.trivia = trivia;
+    }
 
-                if !self.buffer_string.is_empty() {
-                    self.state = LexerState::Start;
-                    self.current_token = self.match_buffer_string();
-                    self.buffer_string = String::new();
-                    break;
-                }
-                self.state = LexerState::End;
-                break;
+    /// Reads the next token. On an error the offending input is skipped, so
+    /// the next call carries on after it.
+    pub fn advance(&mut self) -> Result<&Token, LexError> {
+        let result = match self.lookahead.pop_front() {
+            Some((_, result)) => result,
+            None => self.scan_token(),
+        };
+
+        match result {
+            Ok(SpannedToken { token, span }) => {
+                self.done = matches!(token, Token::EOI);
+                self.current_token = token;
+                self.current_span = span;
+                Ok(self.curr())
             }
+            Err(error) => {
+                self.current_span = error.span;
+                Err(error)
+            }
+        }
+    }
 
-            let current_char = self.input_string.chars().nth(self.position).unwrap();
-            //print!("{}", current_char);
-
-            self.position += 1;
-
-            match self.state {
-                LexerState::Start => match current_char {
-                    'A'..='Z' | 'a'..='z' | '_' => {
-                        self.state = LexerState::Chars;
-                        self.buffer_string.push(current_char);
-                    }
-                    '0'..='9' => {
-                        self.state = LexerState::Numbers;
-                        self.buffer_string.push(current_char);
-                    }
-                    '\'' => {
-                        self.state = LexerState::ReadChar;
-                    }
-                    '"' => {
-                        self.state = LexerState::ReadString;
-                    }
-                    '{' => {
-                        self.current_token = Token::BRACE_L;
-                        break;
-                    }
-                    '}' => {
-                        self.current_token = Token::BRACE_R;
-                        break;
-                    }
-                    '[' => {
-                        self.current_token = Token::BRACKET_L;
-                        break;
-                    }
-                    ']' => {
-                        self.current_token = Token::BRACKET_R;
-                        break;
-                    }
-                    '(' => {
-                        self.current_token = Token::PARENS_L;
-                        break;
-                    }
-                    ')' => {
-                        self.current_token = Token::PARENS_R;
-                        break;
-                    }
-                    '!' => {
-                        self.state = LexerState::Not;
-                    }
-                    '&' => {
-                        self.state = LexerState::And;
-                    }
-                    '|' => {
-                        self.state = LexerState::Or;
-                    }
-                    '.' => {
-                        self.current_token = Token::POINT;
-                        break;
-                    }
-                    ',' => {
-                        self.current_token = Token::COMMA;
-                        break;
-                    }
-                    ':' => {
-                        self.current_token = Token::COLON;
-                        break;
-                    }
-                    ';' => {
-                        self.current_token = Token::SEMICOLON;
-                        break;
-                    }
-                    '+' => {
-                        self.current_token = Token::ADD;
-                        break;
-                    }
-                    '-' => {
-                        self.state = LexerState::Dash;
-                    }
-                    '*' => {
-                        self.current_token = Token::MUL;
-                        break;
-                    }
-                    '/' => {
-                        self.state = LexerState::Slash;
-                    }
-                    '=' => {
-                        self.state = LexerState::Equal;
-                    }
-                    '<' => {
-                        self.state = LexerState::Less;
-                    }
-                    '>' => {
-                        self.state = LexerState::Greater;
-                    }
+    pub fn curr(&self) -> &Token {
+        &self.current_token
+    }
 
-                    _ => {}
-                },
+    /// Advances and returns the token along with its span.
+    pub fn next_token(&mut self) -> Result<SpannedToken, LexError> {
+        let token = self.advance()?.clone();
+        Ok(SpannedToken {
+            token,
+            span: self.current_span,
+        })
+    }
 
-                LexerState::Chars => match current_char {
-                    'A'..'Z' | '_' | 'a'..'z' | '-' | '0'..'9' => {
-                        self.buffer_string.push(current_char);
-                    }
+    /// What the next call to `next` will return, without consuming it.
+    pub fn peek(&mut self) -> Option<&Result<SpannedToken, LexError>> {
+        self.peek_nth(0)
+    }
 
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = self.match_buffer_string();
-                        self.buffer_string = String::new();
+    /// What `next` will return `n + 1` calls from now, so `peek_nth(0)` is
+    /// `peek()`. None past the end of input.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<SpannedToken, LexError>> {
+        if self.done {
+            return None;
+        }
+        while self.lookahead.len() <= n {
+            let ended = matches!(
+                self.lookahead.back(),
+                Some((_, Ok(SpannedToken { token: Token::EOI, .. })))
+            );
+            if ended {
+                return None;
+            }
+            let cursor = self.cursor;
+            let result = self.scan_token();
+            self.lookahead.push_back((cursor, result));
+        }
+        self.lookahead.get(n).map(|(_, result)| result)
+    }
 
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::Numbers => match current_char {
-                    '0'..='9' => {
-                        self.buffer_string.push(current_char);
-                    }
+    /// The current position, before any tokens that were only peeked at.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            cursor: match self.lookahead.front() {
+                Some((cursor, _)) => *cursor,
+                None => self.cursor,
+            },
+            done: self.done,
+            token: self.current_token.clone(),
+            span: self.current_span,
+        }
+    }
 
-                    '.' => {
-                        self.state = LexerState::NumPoint;
-                    }
+    /// Goes back to a checkpoint taken from this lexer, so the tokens after
+    /// it are read again.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.cursor = checkpoint.cursor;
+        self.done = checkpoint.done;
+        self.current_token = checkpoint.token;
+        self.current_span = checkpoint.span;
+        self.lookahead.clear();
+    }
 
-                    _ => {
-                        self.state = LexerState::Start;
-                        let value: i32 = self.buffer_string.parse().unwrap();
-                        self.current_token = Token::LIT_INT32 { value };
-                        self.buffer_string = String::new();
+    /// Prints every token and error, one per line, with its location.
+    /// Returns the number of errors.
+    pub fn print_tokens(&mut self) -> usize {
+        let (tokens, errors) = self.tokens_recovering();
+        let error_count = errors.len();
+        let mut errors = errors.into_iter().peekable();
+        for token in tokens {
+            while let Some(error) = errors.next_if(|error| error.span.start < token.span.start) {
+                println!("{}", error.display());
+            }
+            println!("{}", token.display());
+        }
+        error_count
+    }
 
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::NumPoint => match current_char {
-                    '0'..='9' => {
-                        self.state = LexerState::Decimals;
-                        self.buffer_string.push('.');
-                        self.buffer_string.push(current_char);
-                    }
+    /// Every token up to and including EOI, stopping at the first error.
+    pub fn tokens(&mut self) -> Result<Vec<SpannedToken>, LexError> {
+        self.collect()
+    }
 
-                    _ => {
-                        self.state = LexerState::Start;
-                        let value: i32 = self.buffer_string.parse().unwrap();
-                        self.current_token = Token::LIT_INT32 { value };
-                        self.buffer_string = String::new();
+    /// Every token up to and including EOI, and every error on the way.
+    pub fn tokens_recovering(&mut self) -> (Vec<SpannedToken>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+        (tokens, errors)
+    }
 
-                        self.position -= 2;
-                        break;
-                    }
-                },
-                LexerState::Decimals => match current_char {
-                    '0'..='9' => {
-                        self.buffer_string.push(current_char);
-                    }
+    // Scans one token from the cursor. Outside trivia mode, whitespace and
+    // comments before it are skipped.
+    fn scan_token(&mut self) -> Result<SpannedToken, LexError> {
+        loop {
+            if !self.trivia {
+                self.eat_while(char::is_whitespace);
+            }
+            let start = self.cursor;
+            let result = self.scan();
+            let span = Span {
+                start: start.offset,
+                end: self.cursor.offset,
+                line: start.line,
+                column: start.column,
+            };
+
+            match result {
+                Ok(Token::COMMENT { .. }) if !self.trivia => continue,
+                Ok(token) => return Ok(SpannedToken { token, span }),
+                Err(kind) => return Err(LexError { kind, span }),
+            }
+        }
+    }
 
-                    _ => {
-                        self.state = LexerState::Start;
-                        let value: f32 = self.buffer_string.parse().unwrap();
-                        self.current_token = Token::LIT_FLT32 { value };
-                        self.buffer_string = String::new();
+    fn peek_char(&self) -> Option<char> {
+        self.input_string[self.cursor.offset..].chars().next()
+    }
 
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::ReadChar => match current_char {
-                    '\'' => {
-                        self.state = LexerState::Start;
-                        if self.buffer_string.len() == 1 {
-                            let value = self.buffer_string.chars().nth(0).unwrap();
-                            self.current_token = Token::LIT_CHAR { value };
-                            self.buffer_string = String::new();
-                            break;
-                        }
-                        self.buffer_string = String::new();
-                    }
-                    _ => {
-                        self.buffer_string.push(current_char);
-                    }
-                },
-                // TODO: Error Handling for unclosed chars/strings, invalid chars
-                // TODO: More case testing
-                LexerState::ReadString => match current_char {
-                    '"' => {
-                        self.state = LexerState::Start;
-                        let value = self.buffer_string.clone();
-                        self.current_token = Token::LIT_STRING { value };
-                        self.buffer_string = String::new();
-                        break;
-                    }
-                    _ => {
-                        self.buffer_string.push(current_char);
-                    }
-                },
+    fn peek_second_char(&self) -> Option<char> {
+        self.input_string[self.cursor.offset..].chars().nth(1)
+    }
 
-                LexerState::Not => match current_char {
-                    '=' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::NEQ;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::NOT;
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::And => match current_char {
-                    '&' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::AND;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.position -= 1;
-                    }
-                },
-                LexerState::Or => match current_char {
-                    '|' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::OR;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.position -= 1;
-                    }
-                },
-                LexerState::Dash => match current_char {
-                    '>' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::ARROW_R;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::SUB;
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::Equal => match current_char {
-                    '=' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::EQ;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::ASSIGN;
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::Greater => match current_char {
-                    '=' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::NLT;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::GT;
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::Less => match current_char {
-                    '=' => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::NGT;
-                        break;
-                    }
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::LT;
-                        self.position -= 1;
-                        break;
-                    }
-                },
-                LexerState::Slash => match current_char {
-                    '/' => {
-                        // Comments
-                        self.state = LexerState::Start;
-                    }
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.cursor.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.cursor.line += 1;
+            self.cursor.column = 1;
+        } else {
+            self.cursor.column += 1;
+        }
+        Some(ch)
+    }
 
-                    _ => {
-                        self.state = LexerState::Start;
-                        self.current_token = Token::DIV;
-                        self.position -= 1;
-                        break;
-                    }
-                },
+    // Consumes the next character if it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek_char() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
 
-                _ => {}
-            }
+    // Consumes characters while `predicate` holds and returns them.
+    fn eat_while(&mut self, predicate: impl Fn(char) -> bool) -> &str {
+        let start = self.cursor.offset;
+        while self.peek_char().is_some_and(&predicate) {
+            self.bump();
         }
-        self.curr()
+        &self.input_string[start..self.cursor.offset]
     }
 
-    pub fn curr(&self) -> &Token {
-        &self.current_token
+    fn scan(&mut self) -> Result<Token, LexErrorKind> {
+        let Some(ch) = self.bump() else {
+            return Ok(Token::EOI);
+        };
+
+        let token = match ch {
+            ch if ch.is_whitespace() => Token::WHITESPACE {
+                text: format!("{ch}{}", self.eat_while(char::is_whitespace)),
+            },
+            '/' if self.eat('/') => self.line_comment(),
+            '/' if self.eat('*') => return self.block_comment(),
+            'r' if matches!(self.peek_char(), Some('"' | '#')) => return self.raw_string(),
+            'A'..='Z' | 'a'..='z' | '_' => self.word(),
+            '0'..='9' => return self.number(),
+            '\'' => return self.char_literal(),
+            '"' => return self.string_literal(),
+            '{' => Token::BRACE_L,
+            '}' => Token::BRACE_R,
+            '[' => Token::BRACKET_L,
+            ']' => Token::BRACKET_R,
+            '(' => Token::PARENS_L,
+            ')' => Token::PARENS_R,
+            '.' => Token::POINT,
+            ',' => Token::COMMA,
+            ':' => Token::COLON,
+            ';' => Token::SEMICOLON,
+            '+' => Token::ADD,
+            '*' => Token::MUL,
+            '/' => Token::DIV,
+            '-' if self.eat('>') => Token::ARROW_R,
+            '-' => Token::SUB,
+            '!' if self.eat('=') => Token::NEQ,
+            '!' => Token::NOT,
+            '=' if self.eat('=') => Token::EQ,
+            '=' => Token::ASSIGN,
+            '>' if self.eat('=') => Token::NLT,
+            '>' => Token::GT,
+            '<' if self.eat('=') => Token::NGT,
+            '<' => Token::LT,
+            '&' if self.eat('&') => Token::AND,
+            '|' if self.eat('|') => Token::OR,
+            '&' | '|' => return Err(LexErrorKind::LoneOperator { ch }),
+            ch => return Err(LexErrorKind::UnknownChar { ch }),
+        };
+        Ok(token)
     }
 
-    pub fn print_tokens(&mut self) {
-        println!("");
-        loop {
-            self.advance();
-            if let Token::EOI = self.curr() {
-                break;
+    // The rest of a line after `//`. Exactly three slashes make a doc
+    // comment, as in Rust.
+    fn line_comment(&mut self) -> Token {
+        let text = self.eat_while(|ch| ch != '\n');
+        match text.strip_prefix('/') {
+            Some(doc) if !doc.starts_with('/') => Token::DOC_COMMENT {
+                text: doc.to_string(),
+            },
+            _ => Token::COMMENT {
+                text: format!("//{text}"),
+            },
+        }
+    }
+
+    // The rest of a block comment after `/*`, which may contain nested block
+    // comments.
+    fn block_comment(&mut self) -> Result<Token, LexErrorKind> {
+        let start = self.cursor.offset - 2;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.bump() {
+                None => return Err(LexErrorKind::UnterminatedComment),
+                Some('*') if self.eat('/') => depth -= 1,
+                Some('/') if self.eat('*') => depth += 1,
+                Some(_) => {}
             }
-            print!("{:?}, ", self.curr());
         }
-        print!("{:?}", self.curr());
+        Ok(Token::COMMENT {
+            text: self.input_string[start..self.cursor.offset].to_string(),
+        })
     }
 
-    fn match_buffer_string(&mut self) -> Token {
-        let string = self.buffer_string.as_str();
-        match self.buffer_string.as_str() {
+    // A keyword or identifier, whose first character was just read.
+    fn word(&mut self) -> Token {
+        let start = self.cursor.offset - 1;
+        self.eat_while(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+
+        match &self.input_string[start..self.cursor.offset] {
             "func" => Token::FUNC,
             "let" => Token::LET,
             "if" => Token::IF,
             "else" => Token::ELSE,
             "while" => Token::WHILE,
+            "for" => Token::FOR,
+            "break" => Token::BREAK,
+            "continue" => Token::CONTINUE,
+            "return" => Token::RETURN,
             "print" => Token::PRINT,
             "i32" => Token::TYPE_INT32,
+            "i64" => Token::TYPE_INT64,
+            "u32" => Token::TYPE_UINT32,
+            "u64" => Token::TYPE_UINT64,
             "f32" => Token::TYPE_FLT32,
+            "f64" => Token::TYPE_FLT64,
             "char" => Token::TYPE_CHAR,
+            "bool" => Token::TYPE_BOOL,
+            "true" => Token::LIT_BOOL { value: true },
+            "false" => Token::LIT_BOOL { value: false },
+            name => Token::ID {
+                name: name.to_string(),
+            },
+        }
+    }
+
+    // A number literal, whose first digit was just read: an integer in
+    // decimal, or in hex, octal or binary after `0x`, `0o` or `0b`, or a
+    // decimal float with a fraction or exponent. Digits may be separated by
+    // `_`, and a type suffix such as `i64` or `f64` may follow. Without a
+    // suffix, integers are i32 and floats f32.
+    fn number(&mut self) -> Result<Token, LexErrorKind> {
+        let start = self.cursor.offset - 1;
+        let first = &self.input_string[start..self.cursor.offset];
+        let radix = match (first, self.peek_char()) {
+            ("0", Some('x')) => 16,
+            ("0", Some('o')) => 8,
+            ("0", Some('b')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.bump();
+        }
+        let digits_start = self.cursor.offset - usize::from(radix == 10);
+        self.eat_while(|ch| ch.is_digit(radix) || ch == '_');
+
+        let mut float = false;
+        if radix == 10 {
+            if self.peek_char() == Some('.')
+                && self.peek_second_char().is_some_and(|ch| ch.is_ascii_digit())
+            {
+                self.bump();
+                self.eat_while(|ch| ch.is_ascii_digit() || ch == '_');
+                float = true;
+            }
+            let mut rest = self.input_string[self.cursor.offset..].chars();
+            if matches!(
+                (rest.next(), rest.next(), rest.next()),
+                (Some('e' | 'E'), Some('0'..='9'), _)
+                    | (Some('e' | 'E'), Some('+' | '-'), Some('0'..='9'))
+            ) {
+                self.bump();
+                if !self.eat('+') {
+                    self.eat('-');
+                }
+                self.eat_while(|ch| ch.is_ascii_digit() || ch == '_');
+                float = true;
+            }
+        }
+
+        let digits: String = self.input_string[digits_start..self.cursor.offset]
+            .chars()
+            .filter(|&ch| ch != '_')
+            .collect();
+        let suffix_start = self.cursor.offset;
+        self.eat_while(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+        let literal = self.input_string[start..self.cursor.offset].to_string();
+        let suffix = &self.input_string[suffix_start..self.cursor.offset];
+
+        if suffix.starts_with(|ch: char| ch.is_ascii_digit()) {
+            return Err(LexErrorKind::InvalidDigit { literal, radix });
+        }
+        if digits.is_empty() {
+            return Err(LexErrorKind::MissingDigits { literal });
+        }
+        let ty = match suffix {
+            "" if float => "f32",
+            "" => "i32",
+            "f32" | "f64" if radix == 10 => suffix,
+            "i32" | "i64" | "u32" | "u64" if !float => suffix,
             _ => {
-                if string.contains('.') {
-                    let value = string.parse::<f32>().unwrap();
-                    if value.fract() != 0.0 {
-                        return Token::LIT_FLT32 { value };
-                    } else {
-                        return Token::LIT_INT32 {
-                            value: value as i32,
-                        };
+                let suffix = suffix.to_string();
+                return Err(LexErrorKind::InvalidSuffix { literal, suffix });
+            }
+        };
+
+        let token = match ty {
+            "i32" => i32::from_str_radix(&digits, radix)
+                .ok()
+                .map(|value| Token::LIT_INT32 { value }),
+            "i64" => i64::from_str_radix(&digits, radix)
+                .ok()
+                .map(|value| Token::LIT_INT64 { value }),
+            "u32" => u32::from_str_radix(&digits, radix)
+                .ok()
+                .map(|value| Token::LIT_UINT32 { value }),
+            "u64" => u64::from_str_radix(&digits, radix)
+                .ok()
+                .map(|value| Token::LIT_UINT64 { value }),
+            "f32" => digits
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(|value| Token::LIT_FLT32 { value }),
+            _ => digits
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(|value| Token::LIT_FLT64 { value }),
+        };
+        token.ok_or(LexErrorKind::NumberOverflow {
+            literal,
+            ty: ty.to_string(),
+        })
+    }
+
+    // The rest of a character literal after its opening quote. Unlike a
+    // string, a literal missing its closing quote ends after one character
+    // or at the end of the line, so recovery resumes on the next token.
+    fn char_literal(&mut self) -> Result<Token, LexErrorKind> {
+        let start = self.cursor.offset - 1;
+        let value = match self.peek_char() {
+            None | Some('\n') => return Err(LexErrorKind::UnterminatedChar),
+            Some('\'') => None,
+            Some(_) => match self.bump() {
+                Some('\\') => Some(self.escape()),
+                ch => ch.map(Ok),
+            },
+        };
+
+        // Word characters before a closing quote, as in 'ab', make the
+        // literal malformed rather than unterminated.
+        let extra = !self.eat_while(|ch| ch.is_alphanumeric() || ch == '_').is_empty();
+        if !self.eat('\'') {
+            return Err(LexErrorKind::UnterminatedChar);
+        }
+        match value {
+            Some(value) if !extra => value.map(|value| Token::LIT_CHAR { value }),
+            _ => Err(LexErrorKind::MalformedChar {
+                literal: self.input_string[start..self.cursor.offset].to_string(),
+            }),
+        }
+    }
+
+    // The rest of a string literal after its opening quote.
+    fn string_literal(&mut self) -> Result<Token, LexErrorKind> {
+        let value = self.quoted('"', LexErrorKind::UnterminatedString)?;
+        Ok(Token::LIT_STRING { value })
+    }
+
+    // The rest of a raw string after its `r`: `r"..."`, or `r#"..."#` with
+    // any number of `#` so the contents can hold `"`. No escapes are read.
+    fn raw_string(&mut self) -> Result<Token, LexErrorKind> {
+        let hashes = self.eat_while(|ch| ch == '#').len();
+        if !self.eat('"') {
+            return Err(LexErrorKind::MissingRawQuote);
+        }
+
+        let start = self.cursor.offset;
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let Some(length) = self.input_string[start..].find(&closing) else {
+            while self.bump().is_some() {}
+            return Err(LexErrorKind::UnterminatedString);
+        };
+        let value = self.input_string[start..start + length].to_string();
+        while self.cursor.offset < start + length + closing.len() {
+            self.bump();
+        }
+        Ok(Token::LIT_STRING { value })
+    }
+
+    // Contents of a literal up to the closing quote, with escapes replaced.
+    // An invalid escape is reported once the closing quote is read, so that
+    // recovery resumes after the literal.
+    fn quoted(&mut self, quote: char, unterminated: LexErrorKind) -> Result<String, LexErrorKind> {
+        let mut value = String::new();
+        let mut error = None;
+        loop {
+            match self.bump() {
+                None => return Err(unterminated),
+                Some(ch) if ch == quote => break,
+                Some('\\') => match self.escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(kind) => {
+                        error.get_or_insert(kind);
                     }
-                }
-                if let Ok(value) = string.parse::<i32>() {
-                    return Token::LIT_INT32 { value };
-                }
+                },
+                Some(ch) => value.push(ch),
+            }
+        }
+
+        match error {
+            Some(kind) => Err(kind),
+            None => Ok(value),
+        }
+    }
 
-                return Token::ID {
-                    name: string.to_string(),
+    // The character of an escape sequence whose `\` was just read.
+    fn escape(&mut self) -> Result<char, LexErrorKind> {
+        let start = self.cursor.offset - 1;
+        let ch = match self.bump() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('\\') => '\\',
+            Some('\'') => '\'',
+            Some('"') => '"',
+            Some('0') => '\0',
+            Some('u') => {
+                let code = if self.eat('{') {
+                    let digits = self.eat_while(|ch| ch.is_ascii_hexdigit()).to_string();
+                    let closed = self.eat('}');
+                    (closed && (1..=6).contains(&digits.len()))
+                        .then(|| u32::from_str_radix(&digits, 16).ok())
+                        .flatten()
+                        .and_then(char::from_u32)
+                } else {
+                    None
                 };
+                return code.ok_or_else(|| LexErrorKind::InvalidUnicodeEscape {
+                    escape: self.input_string[start..self.cursor.offset].to_string(),
+                });
             }
+            _ => {
+                return Err(LexErrorKind::UnknownEscape {
+                    escape: self.input_string[start..self.cursor.offset].to_string(),
+                });
+            }
+        };
+        Ok(ch)
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
         }
+        Some(self.next_token())
+    }
+}
+
+impl SpannedToken {
+    pub fn display(&self) -> String {
+        format!("{}:{}\t{:?}", self.span.line, self.span.column, self.token)
+    }
+}
+
+impl LexError {
+    pub fn display(&self) -> String {
+        format!("{}:{}\terror: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnknownChar { ch } => write!(f, "unknown character {ch:?}"),
+            LexErrorKind::LoneOperator { ch } => {
+                write!(f, "lone `{ch}`, did you mean `{ch}{ch}`?")
+            }
+            LexErrorKind::MalformedChar { literal } => write!(
+                f,
+                "character literal {literal} must hold exactly one character"
+            ),
+            LexErrorKind::UnterminatedChar => write!(f, "character literal is never closed"),
+            LexErrorKind::UnterminatedString => write!(f, "string literal is never closed"),
+            LexErrorKind::UnterminatedComment => write!(f, "block comment is never closed"),
+            LexErrorKind::UnknownEscape { escape } => write!(
+                f,
+                "unknown escape `{escape}`, expected one of \\n \\t \\\\ \\' \\\" \\0 \\u{{...}}"
+            ),
+            LexErrorKind::InvalidUnicodeEscape { escape } => write!(
+                f,
+                "invalid unicode escape `{escape}`, expected 1 to 6 hex digits of a valid code point in \\u{{...}}"
+            ),
+            LexErrorKind::MissingRawQuote => write!(f, "raw string must start with `\"` after `r#`"),
+            LexErrorKind::MissingDigits { literal } => {
+                write!(f, "number literal {literal} has no digits")
+            }
+            LexErrorKind::InvalidDigit { literal, radix } => {
+                write!(f, "invalid digit in base {radix} literal {literal}")
+            }
+            LexErrorKind::InvalidSuffix { literal, suffix } => {
+                write!(f, "invalid suffix `{suffix}` on number literal {literal}")
+            }
+            LexErrorKind::NumberOverflow { literal, ty } => {
+                write!(f, "number literal {literal} does not fit in {ty}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl Error for LexError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        Lexer::new(input.to_string())
+            .tokens()
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect()
+    }
+
+    fn error(input: &str) -> LexErrorKind {
+        Lexer::new(input.to_string()).tokens().unwrap_err().kind
+    }
+
+    #[test]
+    fn recovery() {
+        let (tokens, errors) =
+            Lexer::new("let @ x = 'ab';\ny & \"open".to_string()).tokens_recovering();
+        let kinds: Vec<String> = tokens
+            .iter()
+            .map(|token| format!("{:?}", token.token))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "LET",
+                "ID { name: \"x\" }",
+                "ASSIGN",
+                "SEMICOLON",
+                "ID { name: \"y\" }",
+                "EOI"
+            ]
+        );
+
+        let errors: Vec<(usize, usize, LexErrorKind)> = errors
+            .into_iter()
+            .map(|error| (error.span.line, error.span.column, error.kind))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (1, 5, LexErrorKind::UnknownChar { ch: '@' }),
+                (
+                    1,
+                    11,
+                    LexErrorKind::MalformedChar {
+                        literal: "'ab'".to_string()
+                    }
+                ),
+                (2, 3, LexErrorKind::LoneOperator { ch: '&' }),
+                (2, 5, LexErrorKind::UnterminatedString),
+            ]
+        );
+
+        // An unterminated character literal ends at its line.
+        let (tokens, errors) =
+            Lexer::new("let c = 'a;\nlet d = 1;\nlet e = @;".to_string()).tokens_recovering();
+        assert_eq!(tokens.len(), 14);
+        assert!(matches!(tokens[3].token, Token::SEMICOLON));
+        assert!(matches!(tokens[13].token, Token::EOI));
+
+        let errors: Vec<(usize, usize, LexErrorKind)> = errors
+            .into_iter()
+            .map(|error| (error.span.line, error.span.column, error.kind))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (1, 9, LexErrorKind::UnterminatedChar),
+                (3, 9, LexErrorKind::UnknownChar { ch: '@' }),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_literals_and_spans() {
+        let tokens = Lexer::new("\"h😀llo\" 'é'\n  x".to_string())
+            .tokens()
+            .unwrap();
+        assert!(matches!(&tokens[0].token, Token::LIT_STRING { value } if value == "h😀llo"));
+        assert!(matches!(tokens[1].token, Token::LIT_CHAR { value: 'é' }));
+        // Columns count characters, offsets count bytes.
+        assert_eq!((tokens[1].span.line, tokens[1].span.column), (1, 9));
+        assert_eq!((tokens[1].span.start, tokens[1].span.end), (11, 15));
+        assert!(matches!(&tokens[2].token, Token::ID { name } if name == "x"));
+        assert_eq!((tokens[2].span.line, tokens[2].span.column), (2, 3));
+    }
+
+    #[test]
+    fn minus_ends_an_identifier() {
+        let tokens = Lexer::new("x-1".to_string()).tokens().unwrap();
+        assert!(matches!(&tokens[0].token, Token::ID { name } if name == "x"));
+        assert!(matches!(tokens[1].token, Token::SUB));
+        assert!(matches!(tokens[2].token, Token::LIT_INT32 { value: 1 }));
+    }
+
+    #[test]
+    fn peek_and_rewind() {
+        let mut lexer = Lexer::new("let x = 1;".to_string());
+        assert!(matches!(
+            lexer.peek_nth(1),
+            Some(Ok(SpannedToken {
+                token: Token::ID { .. },
+                ..
+            }))
+        ));
+        assert!(matches!(lexer.advance(), Ok(Token::LET)));
+        let checkpoint = lexer.checkpoint();
+        assert!(matches!(lexer.advance(), Ok(Token::ID { .. })));
+        assert!(matches!(lexer.advance(), Ok(Token::ASSIGN)));
+        lexer.rewind(checkpoint);
+        assert!(matches!(lexer.curr(), Token::LET));
+        assert_eq!(lexer.current_span.start, 0);
+        assert!(matches!(lexer.advance(), Ok(Token::ID { .. })));
+        assert_eq!(lexer.count(), 4); // = 1 ; EOI
+    }
+
+    #[test]
+    fn comments() {
+        let tokens = tokens("a // b c\n/* x /* y */ z */ d /// doc\n//// not doc\ne");
+        assert!(matches!(&tokens[0], Token::ID { name } if name == "a"));
+        assert!(matches!(&tokens[1], Token::ID { name } if name == "d"));
+        assert!(matches!(&tokens[2], Token::DOC_COMMENT { text } if text == " doc"));
+        assert!(matches!(&tokens[3], Token::ID { name } if name == "e"));
+        assert!(matches!(tokens[4], Token::EOI));
+
+        assert_eq!(error("/* /* */"), LexErrorKind::UnterminatedComment);
+    }
+
+    #[test]
+    fn trivia_covers_the_input() {
+        let input = "let /* c */ x; // end\n";
+        let tokens = Lexer::with_trivia(input.to_string()).tokens().unwrap();
+        let covered: usize = tokens
+            .iter()
+            .map(|token| token.span.end - token.span.start)
+            .sum();
+        assert_eq!(covered, input.len());
+        assert!(matches!(&tokens[2].token, Token::COMMENT { text } if text == "/* c */"));
+    }
+
+    #[test]
+    fn escapes() {
+        let tokens = tokens(r##""a\"b\n\t\\\0\u{1F600}" '\'' '\n' r#"raw "quoted" \n"#"##);
+        assert!(matches!(&tokens[0], Token::LIT_STRING { value } if value == "a\"b\n\t\\\0😀"));
+        assert!(matches!(tokens[1], Token::LIT_CHAR { value: '\'' }));
+        assert!(matches!(tokens[2], Token::LIT_CHAR { value: '\n' }));
+        assert!(matches!(&tokens[3], Token::LIT_STRING { value } if value == r#"raw "quoted" \n"#));
+
+        assert_eq!(
+            error(r#""\q""#),
+            LexErrorKind::UnknownEscape {
+                escape: "\\q".to_string()
+            }
+        );
+        assert_eq!(
+            error(r#""\u{110000}""#),
+            LexErrorKind::InvalidUnicodeEscape {
+                escape: "\\u{110000}".to_string()
+            }
+        );
+        assert_eq!(
+            error(r"'\q'"),
+            LexErrorKind::UnknownEscape {
+                escape: "\\q".to_string()
+            }
+        );
+        assert_eq!(error(r#""a\""#), LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn numbers() {
+        let tokens = tokens(
+            "1_000 0xff 0o17 0b1010 2.5e3 10i64 7u32 2.5f64 2147483647 18446744073709551615u64",
+        );
+        assert!(matches!(tokens[0], Token::LIT_INT32 { value: 1000 }));
+        assert!(matches!(tokens[1], Token::LIT_INT32 { value: 255 }));
+        assert!(matches!(tokens[2], Token::LIT_INT32 { value: 15 }));
+        assert!(matches!(tokens[3], Token::LIT_INT32 { value: 10 }));
+        assert!(matches!(tokens[4], Token::LIT_FLT32 { value } if value == 2500.0));
+        assert!(matches!(tokens[5], Token::LIT_INT64 { value: 10 }));
+        assert!(matches!(tokens[6], Token::LIT_UINT32 { value: 7 }));
+        assert!(matches!(tokens[7], Token::LIT_FLT64 { value } if value == 2.5));
+        assert!(matches!(tokens[8], Token::LIT_INT32 { value: i32::MAX }));
+        assert!(matches!(tokens[9], Token::LIT_UINT64 { value: u64::MAX }));
+    }
+
+    #[test]
+    fn numeric_overflow() {
+        let overflow = |literal: &str, ty: &str| LexErrorKind::NumberOverflow {
+            literal: literal.to_string(),
+            ty: ty.to_string(),
+        };
+        assert_eq!(error("2147483648"), overflow("2147483648", "i32"));
+        assert_eq!(
+            error("0x1_0000_0000u32"),
+            overflow("0x1_0000_0000u32", "u32")
+        );
+        assert_eq!(error("1e40"), overflow("1e40", "f32"));
+        assert_eq!(
+            error("0b102"),
+            LexErrorKind::InvalidDigit {
+                literal: "0b102".to_string(),
+                radix: 2
+            }
+        );
     }
 }
@@ -1,29 +1,66 @@
 use colored::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
+use std::io::{self, Write};
 use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
 
-use crate::{grammar::{Derivation, Grammar, Rule}, lexer::{Lexer, Token}};
+use crate::{automata::Dfa, bnf::Notation, diff::GrammarDiff, grammar::{Derivation, Grammar, Rule}, graph::DependencyGraph, lexer::{Lexer, Token}, lint::Severity, lr::{Action, Table, explain_lalr, lalr_verdict}, pumping::{Decomposition, Pumping}};
 
+pub mod automata;
+pub mod bnf;
+pub mod diff;
+pub mod export;
 pub mod grammar;
+pub mod graph;
 pub mod lexer;
+pub mod lint;
+pub mod lr;
+pub mod pumping;
 
 pub enum Command {
     Help { help_command: Option<String> },
     Print { file_path: String, numbered: bool },
     List { list_command: Option<String> },
-    Derive { derive_command: String },
-    Tokenize { file_path: String }
+    Derive { derive_command: String, derive_args: Vec<String> },
+    Tokenize { file_path: String, recover: bool, trivia: bool },
+    Combine { operation: String, file_paths: Vec<String> },
+    Intersect { pattern: String },
+    Table { table_kind: String },
+    Parse { table_kind: String, word: String },
+    Import { notation: Notation, file_path: String },
+    Export { target: String, name: String },
+    Diff { old_path: String, new_path: String },
+    Check,
+    Graph { graph_format: String },
+    Pump { word: String },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
 }
 
 pub struct Config {
     pub command: Command,
     pub grammar: Grammar,
+    pub grammar_path: Option<String>,
+    pub format: OutputFormat,
 }
 
 impl Config {
     pub fn build(args: &[String]) -> Result<Config, &'static str> {
+        let mut args = args.to_vec();
+        let grammar_path = take_option(&mut args, "--grammar")?;
+        let format = match take_option(&mut args, "--format")?.as_deref() {
+            None | Some("text") => OutputFormat::Text,
+            Some("json") => OutputFormat::Json,
+            Some(_) => return Err("Unknown output format"),
+        };
+
         if args.len() < 2 {
             return Err("Not enough arguments");
         }
@@ -50,12 +87,7 @@ impl Config {
                 }
 
                 let file_path = args[2].clone();
-                let numbered: bool;
-                if args.len() > 3 {
-                    numbered = args[3].to_lowercase() == "--numbered";
-                } else {
-                    numbered = false;
-                }
+                let numbered = args.len() > 3 && args[3].to_lowercase() == "--numbered";
                 Command::Print {
                     file_path,
                     numbered,
@@ -71,29 +103,178 @@ impl Config {
                 }
 
                 let derive_command = args[2].clone();
-                Command::Derive { derive_command }
+                let derive_args = args[3..].to_vec();
+                Command::Derive {
+                    derive_command,
+                    derive_args,
+                }
             }
             "tokenize" => {
                 if args.len() < 3 {
                     return Err("Enter File Path");
                 }
                 let file_path = args[2].clone();
-                Command::Tokenize { file_path }
+                let has_flag = |flag: &str| args[3..].iter().any(|arg| arg.to_lowercase() == flag);
+                let recover = has_flag("--recover");
+                let trivia = has_flag("--trivia");
+                Command::Tokenize {
+                    file_path,
+                    recover,
+                    trivia,
+                }
+            }
+            "combine" => {
+                if args.len() < 3 {
+                    return Err("Enter Combine Operation");
+                }
+
+                let operation = args[2].to_lowercase();
+                let file_count = match operation.as_str() {
+                    "union" | "concat" => 2,
+                    "star" | "reverse" => 1,
+                    _ => return Err("Unknown combine operation"),
+                };
+                if args.len() != 3 + file_count {
+                    return Err("Wrong number of grammar files for combine");
+                }
+
+                let file_paths = args[3..].to_vec();
+                Command::Combine {
+                    operation,
+                    file_paths,
+                }
+            }
+            "intersect" => {
+                if args.len() < 3 {
+                    return Err("Enter Pattern");
+                }
+                let pattern = args[2].clone();
+                Command::Intersect { pattern }
+            }
+            "table" => {
+                if args.len() < 3 {
+                    return Err("Enter Table Kind");
+                }
+                let table_kind = args[2].to_lowercase();
+                Command::Table { table_kind }
+            }
+            "parse" => {
+                if args.len() < 4 {
+                    return Err("Enter Table Kind and Word");
+                }
+                let table_kind = args[2].to_lowercase();
+                let word = args[3].clone();
+                Command::Parse { table_kind, word }
+            }
+            "import" => {
+                if args.len() < 4 {
+                    return Err("Enter Notation and File Path");
+                }
+                let notation = match args[2].to_lowercase().as_str() {
+                    "bnf" => Notation::Bnf,
+                    "ebnf" => Notation::Ebnf,
+                    _ => return Err("Unknown notation"),
+                };
+                let file_path = args[3].clone();
+                Command::Import {
+                    notation,
+                    file_path,
+                }
+            }
+            "export" => {
+                if args.len() < 3 {
+                    return Err("Enter Export Format");
+                }
+                let target = args[2].to_lowercase();
+                let name = args.get(3).cloned().unwrap_or("rose".to_string());
+                Command::Export { target, name }
+            }
+            "diff" => {
+                if args.len() < 4 {
+                    return Err("Enter Old and New Grammar Files");
+                }
+                let old_path = args[2].clone();
+                let new_path = args[3].clone();
+                Command::Diff { old_path, new_path }
+            }
+            "check" => Command::Check,
+            "pump" => {
+                if args.len() < 3 {
+                    return Err("Enter Word");
+                }
+                let word = args[2].clone();
+                Command::Pump { word }
+            }
+            "graph" => {
+                let graph_format = args
+                    .get(2)
+                    .map(|arg| arg.to_lowercase())
+                    .unwrap_or("text".to_string());
+                Command::Graph { graph_format }
             }
             _ => return Err("Unknown command"),
         };
 
-        Ok(Config { command, grammar })
+        Ok(Config {
+            command,
+            grammar,
+            grammar_path,
+            format,
+        })
     }
 }
 
+// Removes `name value` from the arguments, wherever it appears.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, &'static str> {
+    let Some(index) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        return Err("Missing value for option");
+    }
+
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let (grammar, lines) = match &config.grammar_path {
+        Some(path) => load_grammar_with_lines(path)?,
+        None => (config.grammar, None),
+    };
+
+    let format = config.format;
+
     match config.command {
         Command::Help { help_command } => help(help_command)?,
         Command::Print {  file_path, numbered} => print(file_path, numbered)?,
-        Command::List { list_command } => list(&config.grammar, list_command)?,
-        Command::Derive { derive_command } => derive(&config.grammar, derive_command)?,
-        Command::Tokenize { file_path } => tokenize(file_path),
+        Command::List { list_command } => list(&grammar, list_command, format)?,
+        Command::Derive {
+            derive_command,
+            derive_args,
+        } => derive(&grammar, derive_command, derive_args, format)?,
+        Command::Tokenize {
+            file_path,
+            recover,
+            trivia,
+        } => tokenize(file_path, recover, trivia, format)?,
+        Command::Combine {
+            operation,
+            file_paths,
+        } => combine(operation, file_paths, format)?,
+        Command::Intersect { pattern } => intersect(&grammar, pattern, format)?,
+        Command::Table { table_kind } => table(&grammar, table_kind, format)?,
+        Command::Parse { table_kind, word } => parse(&grammar, table_kind, word, format)?,
+        Command::Import {
+            notation,
+            file_path,
+        } => import(notation, file_path, format)?,
+        Command::Export { target, name } => export(&grammar, target, name, format)?,
+        Command::Diff { old_path, new_path } => diff(old_path, new_path, format)?,
+        Command::Check => check(&grammar, lines.as_deref(), format)?,
+        Command::Graph { graph_format } => graph(&grammar, graph_format, format)?,
+        Command::Pump { word } => pump(&grammar, word, format)?,
     }
 
     Ok(())
@@ -122,8 +303,8 @@ pub fn print(path: String, numbered: bool) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn help(help_command: Option<String>) -> Result<(), Box<dyn Error>> {
-    if help_command.is_some() {
-        let help_command = help_command.unwrap().to_lowercase();
+    if let Some(help_command) = help_command {
+        let help_command = help_command.to_lowercase();
         if help_command == "print" {
             println!(
                 "
@@ -140,13 +321,178 @@ Prints the contents of a file.
             println!("
 - Prints all grammar rules with {} keyword.
 - Prints all tokens with {} keyword.
+- Prints every word of a finite grammar with {} keyword.
 - Prints all commands when no keyword is given.
-                ", "rules".yellow(), "tokens".yellow()
+                ", "rules".yellow(), "tokens".yellow(), "language".yellow()
             );
         }else if help_command == "derive"{
-            println!("Creates a word from the grammar.")
+            println!(
+                "
+Creates a word from the grammar.
+
+{}
+{}
+{}
+
+- random applies random rules to the leftmost nonterminal.
+- replay applies the given rule indices to the leftmost nonterminal in order
+  and reports the first rule that does not apply.
+- interactive shows the applicable rules at each step and lets you pick one.
+  Enter a rule index, u to undo, r to redo or q to quit. Once the word is
+  complete it keeps asking, so the last step can still be undone.
+                ",
+                "derive random".yellow(),
+                "derive replay 'rule,rule,...'".yellow(),
+                "derive interactive".yellow()
+            );
         } else if help_command == "tokenize"{
-            println!("Creates tokens from user inputted file.")
+            println!(
+                "
+Creates tokens from user inputted file, each printed with its line and column.
+
+{}
+
+- Stops at the first lexical error unless --recover is given, which skips
+  past each error and reports all of them.
+- --trivia also prints comments and whitespace as tokens.
+                ",
+                "tokenize 'file_path' (--recover) (--trivia)".yellow()
+            );
+        } else if help_command == "combine" {
+            println!(
+                "
+Builds a new grammar from grammar files and prints it in the grammar file format.
+
+{}
+{}
+{}
+{}
+                ",
+                "combine union 'file_path' 'file_path'".yellow(),
+                "combine concat 'file_path' 'file_path'".yellow(),
+                "combine star 'file_path'".yellow(),
+                "combine reverse 'file_path'".yellow()
+            );
+        } else if help_command == "intersect" {
+            println!(
+                "
+Prints the part of the grammar whose words match a pattern.
+
+{}
+
+- Patterns use literals, . for any terminal, (), |, *, + and ?.
+                ",
+                "intersect 'pattern'".yellow()
+            );
+        } else if help_command == "table" {
+            println!(
+                "
+Builds an LR parsing table for the grammar and explains its conflicts.
+
+{}
+
+- slr prints the LR(0) item sets, the SLR(1) table and any conflicts.
+- lr1 prints the canonical LR(1) item sets, table and conflicts.
+- lalr prints the LALR(1) item sets, table and conflicts, then compares
+  table sizes and conflicts with the SLR(1) and LR(1) tables.
+- Shift/reduce conflicts are resolved with %left, %right and %nonassoc
+  declarations from the grammar file when possible.
+                ",
+                "table slr|lr1|lalr".yellow()
+            );
+        } else if help_command == "parse" {
+            println!(
+                "
+Parses a word with an LR table, printing the stack, input and action of
+every step, then the rightmost derivation found by the parser.
+
+{}
+                ",
+                "parse slr|lr1|lalr 'word'".yellow()
+            );
+        } else if help_command == "import" {
+            println!(
+                "
+Reads a BNF or EBNF specification and prints it in the grammar file format.
+
+{}
+
+- BNF rules look like <expr> ::= <expr> \"+\" <term> | <term>
+- EBNF rules look like expr = term , {{ \"+\" , term }} ;
+- Each name becomes one uppercase character, listed in comments at the top.
+                ",
+                "import bnf|ebnf 'file_path'".yellow()
+            );
+        } else if help_command == "export" {
+            println!(
+                "
+Prints the grammar as input for a parser generator.
+
+{}
+
+- bison prints a .y file, antlr a .g4 file and tree-sitter a grammar.js.
+- The name is used for the ANTLR and tree-sitter grammar names.
+- tree-sitter rules other than the first may not match the empty string, so
+  nullable nonterminals become optional(...) where they are used.
+                ",
+                "export bison|antlr|tree-sitter ('name')".yellow()
+            );
+        } else if help_command == "diff" {
+            println!(
+                "
+Compares two grammar files: start symbol, added, removed and changed rules,
+terminals and nonterminals, and sample words only one of them generates.
+
+{}
+
+- Words are compared up to length {}, or shorter when there are too many
+  to enumerate quickly.
+                ",
+                "diff 'old_file_path' 'new_file_path'".yellow(),
+                diff::SAMPLE_LENGTH
+            );
+        } else if help_command == "check" {
+            println!(
+                "
+Lints the grammar and reports each problem with its rule index and the line
+of the grammar file it comes from.
+
+{}
+
+- Errors: undefined nonterminals and rules that never derive a word.
+- Warnings: unreachable, duplicate, ambiguous and left-recursive rules, and
+  a start symbol used on a right-hand side.
+- Exits with an error if any errors were found.
+                ",
+                "check".yellow()
+            );
+        } else if help_command == "graph" {
+            println!(
+                "
+Prints which nonterminals each nonterminal's rules use, with the indices of
+the rules on each edge. Strongly connected components with a cycle are
+marked as recursive.
+
+{}
+
+- text prints an adjacency list, dot prints Graphviz source.
+                ",
+                "graph (text|dot)".yellow()
+            );
+        } else if help_command == "pump" {
+            println!(
+                "
+Splits a word of the grammar like the pumping lemma and prints the pumped
+words with their leftmost derivations.
+
+{}
+
+- Regular grammars, where every rule is right-linear or every rule is
+  left-linear, give xyz with xy^iz in the language.
+- Other grammars give uvwxy with uv^iwx^iy in the language.
+                ",
+                "pump 'word'".yellow()
+            );
         } else {
             println!("{}", "Command not found.".red());
         }
@@ -158,29 +504,78 @@ Prints the contents of a file.
 {}\t\tPrints all commands
 {}\t\tCreates word from grammar
 {}\tCreates tokens from inputted language
+{}\t\tBuilds a grammar from grammar files
+{}\tIntersects grammar with a regular pattern
+{}\t\tBuilds an LR parsing table for grammar
+{}\t\tParses a word with an LR table
+{}\t\tReads a grammar from BNF or EBNF
+{}\t\tPrints grammar for a parser generator
+{}\t\tCompares two grammar files
+{}\t\tReports problems in grammar
+{}\t\tPrints graph of nonterminal dependencies
+{}\t\tSplits a word like the pumping lemma
 ",
             "HELP".yellow(),
             "PRINT".yellow(),
             "LIST".yellow(),
             "DERIVE".yellow(),
-            "TOKENIZE".yellow()
+            "TOKENIZE".yellow(),
+            "COMBINE".yellow(),
+            "INTERSECT".yellow(),
+            "TABLE".yellow(),
+            "PARSE".yellow(),
+            "IMPORT".yellow(),
+            "EXPORT".yellow(),
+            "DIFF".yellow(),
+            "CHECK".yellow(),
+            "GRAPH".yellow(),
+            "PUMP".yellow()
         );
     }
 
     Ok(())
 }
 
-pub fn list(grammar: &Grammar, list_command: Option<String>) -> Result<(), Box<dyn Error>> {
-    if list_command.is_some() {
-        let list_command = list_command.unwrap().to_lowercase();
-        if list_command == "rules" {
-            for (_, rule) in grammar.rules.iter().enumerate() {
+pub fn list(
+    grammar: &Grammar,
+    list_command: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(list_command) = list_command {
+        let list_command = list_command.to_lowercase();
+        if format == OutputFormat::Json {
+            match list_command.as_str() {
+                "rules" => print_json(grammar)?,
+                "tokens" => print_json(&Token::iter().collect::<Vec<Token>>())?,
+                "language" => print_json(&json!({
+                    "empty": grammar.is_empty(),
+                    "finite": grammar.is_finite(),
+                    "words": grammar.finite_language(),
+                }))?,
+                _ => {}
+            }
+        } else if list_command == "rules" {
+            for rule in &grammar.rules {
                 println!("{}", rule.display())
             }
         }else if list_command == "tokens" {
             for token in Token::iter(){
                 println!("{:?}", token);
             }
+        } else if list_command == "language" {
+            if grammar.is_empty() {
+                println!("{}", "The grammar generates no words.".red());
+            } else if let Some(words) = grammar.finite_language() {
+                for word in words {
+                    if word.is_empty() {
+                        println!("(empty word)");
+                    } else {
+                        println!("{word}");
+                    }
+                }
+            } else {
+                println!("{}", "The grammar generates infinitely many words.".yellow());
+            }
         }
     } else {
         println!(
@@ -190,35 +585,586 @@ pub fn list(grammar: &Grammar, list_command: Option<String>) -> Result<(), Box<d
     {}
     {}
     {}
+    {}
+    {}
+    {}
+    {}
+    {}
+    {}
+    {}
+    {}
+    {}
+    {}
     ",
             "HELP".yellow(),
             "PRINT".yellow(),
             "LIST".yellow(),
             "DERIVE".yellow(),
-            "TOKENIZE".yellow()
+            "TOKENIZE".yellow(),
+            "COMBINE".yellow(),
+            "INTERSECT".yellow(),
+            "TABLE".yellow(),
+            "PARSE".yellow(),
+            "IMPORT".yellow(),
+            "EXPORT".yellow(),
+            "DIFF".yellow(),
+            "CHECK".yellow(),
+            "GRAPH".yellow(),
+            "PUMP".yellow()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn derive(
+    grammar: &Grammar,
+    derive_command: String,
+    derive_args: Vec<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let derive_command = derive_command.to_lowercase();
+    if derive_command == "replay" {
+        let Some(sequence) = derive_args.first() else {
+            return Err("Enter rule indices, e.g. 0,3,4,5".into());
+        };
+        let mut rule_indices = Vec::new();
+        for index in sequence.split(',').filter(|index| !index.trim().is_empty()) {
+            rule_indices.push(index.trim().parse::<usize>()?);
+        }
+
+        let mut derivation = Derivation::new(grammar);
+        let result = derivation.replay(grammar, &rule_indices);
+        if format == OutputFormat::Json {
+            return print_json(&json!({
+                "complete": derivation.is_complete(),
+                "error": result.as_ref().err().map(|error| error.to_string()),
+                "derivation": derivation,
+            }));
+        }
+
+        print!("{}", derivation.get_history());
+        match result {
+            Err(error) => println!("{}", format!("Invalid {error}").red()),
+            Ok(()) if derivation.is_complete() => {
+                println!("{} {}", "Derived word:".green(), derivation.word().yellow())
+            }
+            Ok(()) => println!(
+                "{}",
+                "The rules ran out before the word was complete.".yellow()
+            ),
+        }
+    } else if derive_command == "interactive" {
+        derive_interactive(grammar, format)?;
+    } else if derive_command == "random" {
+        if grammar.is_empty() && format == OutputFormat::Text {
+            println!("{}", "The grammar generates no words.".red());
+            return Ok(());
+        }
+
+        let mut derivation = Derivation::new(grammar);
+        if format == OutputFormat::Json {
+            let result = derivation.print_random(grammar, Some(20));
+            return print_json(&json!({
+                "word": result.as_ref().ok(),
+                "error": result.as_ref().err().map(|error| error.to_string()),
+                "derivation": derivation,
+            }));
+        }
+        match derivation.print_random(grammar, Some(20)) {
+            Ok(word) => println!("Random Derived Word: {}", word.yellow()),
+            Err(error) => println!("{}", format!("No Word Generated: {error}").red()),
+        }
+    }
+
+    Ok(())
+}
+
+// Reads rule choices from stdin until the word is complete, the user quits
+// or input ends. Undone rule indices are kept for redo until a new rule is
+// applied.
+fn derive_interactive(grammar: &Grammar, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let mut derivation = Derivation::new(grammar);
+    let mut undone: Vec<usize> = Vec::new();
+    let mut lines = io::stdin().lines();
+
+    // Keeps prompting once the word is complete, so the last step can still
+    // be undone.
+    loop {
+        let nt = derivation.leftmost_nonterminal();
+        let rule_idxs = nt.map(|nt| grammar.rule_idxs_from_nt(nt)).unwrap_or_default();
+
+        println!("\n{}", highlight_leftmost(&derivation));
+        match nt {
+            Some(nt) if rule_idxs.is_empty() => println!("{}", format!("No rules rewrite {nt}.").red()),
+            Some(_) => {}
+            None => println!("{}", "The word is complete.".green()),
+        }
+        for &rule_index in &rule_idxs {
+            println!("  {}: {}", rule_index, grammar.rules[rule_index as usize].display());
+        }
+        let prompt = if nt.is_some() { "rule, u, r or q>" } else { "u, r or q>" };
+        print!("{} ", prompt.blue());
+        io::stdout().flush()?;
+
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        match line?.trim() {
+            "q" | "quit" => break,
+            "u" | "undo" => match derivation.undo() {
+                Some(rule_index) => undone.push(rule_index),
+                None => println!("{}", "Nothing to undo.".yellow()),
+            },
+            "r" | "redo" => match undone.pop() {
+                Some(rule_index) => derivation.derive_leftmost(grammar, rule_index)?,
+                None => println!("{}", "Nothing to redo.".yellow()),
+            },
+            input => match (nt, input.parse::<usize>()) {
+                (Some(_), Ok(rule_index)) if rule_idxs.contains(&(rule_index as i32)) => {
+                    derivation.derive_leftmost(grammar, rule_index)?;
+                    undone.clear();
+                }
+                (Some(nt), _) => println!("{}", format!("Enter one of the rules for {nt}.").red()),
+                (None, _) => println!("{}", "Enter u to undo, r to redo or q to quit.".red()),
+            },
+        }
+    }
+
+    if format == OutputFormat::Json {
+        return print_json(&json!({
+            "complete": derivation.is_complete(),
+            "derivation": derivation,
+        }));
+    }
+
+    println!("\n{}", derivation.get_history());
+    if derivation.is_complete() {
+        println!("{} {}", "Derived word:".green(), derivation.word().yellow());
+    }
+    Ok(())
+}
+
+// The current sentential form with its leftmost nonterminal highlighted.
+fn highlight_leftmost(derivation: &Derivation) -> String {
+    let (_, sentential) = derivation.steps.last().unwrap();
+    if sentential.is_complete() {
+        return sentential.form.clone();
+    }
+
+    let chars: Vec<char> = sentential.form.chars().collect();
+    let index = sentential.first_nt_index as usize;
+    let before: String = chars[..index].iter().collect();
+    let after: String = chars[index + 1..].iter().collect();
+    format!(
+        "{before}{}{after}",
+        chars[index].to_string().yellow().bold().underline()
+    )
+}
+
+pub fn tokenize(
+    path: String,
+    recover: bool,
+    trivia: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let mut lexer = if trivia {
+        Lexer::with_trivia(contents)
+    } else {
+        Lexer::new(contents)
+    };
+
+    if !recover {
+        let tokens = lexer.tokens()?;
+        match format {
+            OutputFormat::Text => {
+                for token in &tokens {
+                    println!("{}", token.display());
+                }
+            }
+            OutputFormat::Json => print_json(&tokens)?,
+        }
+        return Ok(());
+    }
+
+    let error_count = match format {
+        OutputFormat::Text => lexer.print_tokens(),
+        OutputFormat::Json => {
+            let (tokens, errors) = lexer.tokens_recovering();
+            print_json(&json!({ "tokens": tokens, "errors": errors }))?;
+            errors.len()
+        }
+    };
+    if error_count > 0 {
+        return Err(format!("tokenize found {error_count} errors").into());
+    }
+    Ok(())
+}
+
+pub fn combine(
+    operation: String,
+    file_paths: Vec<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let mut grammars = Vec::new();
+    for path in &file_paths {
+        grammars.push(load_grammar(path)?);
+    }
+
+    let combined = match operation.as_str() {
+        "union" => grammars[0].union(&grammars[1])?,
+        "concat" => grammars[0].concatenation(&grammars[1])?,
+        "star" => grammars[0].star()?,
+        "reverse" => grammars[0].reversal(),
+        _ => return Err("Unknown combine operation".into()),
+    };
+    print_grammar(&combined, format)
+}
+
+pub fn intersect(
+    grammar: &Grammar,
+    pattern: String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let dfa = Dfa::from_pattern(&pattern, &grammar.terminals)?;
+    let product = grammar.intersect(&dfa)?;
+
+    if format == OutputFormat::Json {
+        print_json(&product)?;
+    } else if product.rules.is_empty() {
+        println!("{}", "No word of the grammar matches the pattern.".red());
+    } else {
+        print!("{}", product.to_source());
+    }
+
+    Ok(())
+}
+
+pub fn table(
+    grammar: &Grammar,
+    table_kind: String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let table = build_table(grammar, &table_kind)?;
+    let comparison = if table_kind == "lalr" {
+        Some((Table::slr(grammar)?, Table::lr1(grammar)?))
+    } else {
+        None
+    };
+
+    if format == OutputFormat::Json {
+        let augmented = &table.automaton.grammar;
+        let actions = |row: &BTreeMap<char, Action>| -> BTreeMap<char, String> {
+            row.iter()
+                .map(|(&symbol, action)| (symbol, action.to_string()))
+                .collect()
+        };
+        let conflicts: Vec<_> = table
+            .conflicts
+            .iter()
+            .map(|conflict| {
+                json!({
+                    "state": conflict.state,
+                    "symbol": conflict.symbol,
+                    "actions": conflict.actions.iter().map(|action| action.to_string()).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let resolved: Vec<_> = table
+            .resolved
+            .iter()
+            .map(|resolution| {
+                json!({
+                    "state": resolution.conflict.state,
+                    "symbol": resolution.conflict.symbol,
+                    "chosen": resolution.chosen.map(|action| action.to_string()),
+                    "reason": resolution.reason,
+                })
+            })
+            .collect();
+        return print_json(&json!({
+            "kind": table_kind,
+            "rules": grammar.rules,
+            "states": table.automaton.states.iter().map(|items| {
+                items.iter().map(|item| item.display(augmented)).collect::<Vec<_>>()
+            }).collect::<Vec<_>>(),
+            "action": table.action.iter().map(actions).collect::<Vec<_>>(),
+            "goto": table.goto,
+            "conflicts": conflicts,
+            "resolved": resolved,
+            "verdict": comparison.as_ref().map(|(slr, lr1)| lalr_verdict(slr, &table, lr1)),
+        }));
+    }
+
+    for (i, rule) in grammar.rules.iter().enumerate() {
+        println!("{} {}", format!("{i}:").yellow(), rule.display());
+    }
+    println!();
+    println!("{}", table.automaton.display());
+    println!("{}", table.display());
+
+    if !table.resolved.is_empty() {
+        println!("{}", format!("Resolved by precedence: {}", table.resolved.len()).green());
+        for resolution in &table.resolved {
+            print!("{}", resolution.display(&table.automaton.grammar));
+        }
+    }
+
+    if table.conflicts.is_empty() {
+        println!("{}", "No conflicts.".green());
+    } else {
+        println!("{}", format!("Conflicts: {}", table.conflicts.len()).red());
+        for conflict in &table.conflicts {
+            print!("{}", conflict.display(&table.automaton.grammar));
+        }
+    }
+
+    if let Some((slr, lr1)) = comparison {
+        println!();
+        for (name, table) in [("SLR(1)", &slr), ("LALR(1)", &table), ("LR(1)", &lr1)] {
+            println!(
+                "{:<8} {} states, {} actions, {} gotos, {} conflicts, {} resolved by precedence",
+                name.yellow(),
+                table.state_count(),
+                table.action_count(),
+                table.goto_count(),
+                table.conflicts.len(),
+                table.resolved.len()
+            );
+        }
+
+        println!("{}", lalr_verdict(&slr, &table, &lr1).green());
+        print!("{}", explain_lalr(&slr, &table, &lr1));
+    }
+
+    Ok(())
+}
+
+pub fn parse(
+    grammar: &Grammar,
+    table_kind: String,
+    word: String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let table = build_table(grammar, &table_kind)?;
+    let trace = table.trace(&word);
+
+    if format == OutputFormat::Json {
+        return print_json(&json!({
+            "accepted": trace.is_accepted(),
+            "error": trace.error.as_ref().map(|error| error.to_string()),
+            "reductions": trace.reductions,
+            "derivation": trace.derivation(grammar),
+        }));
+    }
+
+    if !table.conflicts.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "The table has {} conflicts; using shift over reduce and the earliest rule.",
+                table.conflicts.len()
+            )
+            .yellow()
         );
     }
 
+    print!("{}", trace.display(&table.automaton.grammar));
+    println!();
+
+    match (&trace.error, trace.derivation(grammar)) {
+        (None, Some(derivation)) => {
+            println!("{}", "Accepted. Rightmost derivation:".green());
+            print!("{}", derivation.get_history());
+        }
+        (Some(error), _) => println!("{}", format!("Rejected: {error}").red()),
+        (None, None) => println!("{}", "Accepted, but the derivation could not be rebuilt.".red()),
+    }
+
+    Ok(())
+}
+
+pub fn import(notation: Notation, path: String, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let imported = bnf::import(&contents, notation)?;
+
+    if format == OutputFormat::Json {
+        let names: BTreeMap<String, char> = imported.names.into_iter().collect();
+        return print_json(&json!({ "names": names, "grammar": imported.grammar }));
+    }
+
+    for (name, nonterminal) in &imported.names {
+        println!("# {nonterminal} = {name}");
+    }
+    print!("{}", imported.grammar.to_source());
+
+    Ok(())
+}
+
+pub fn export(
+    grammar: &Grammar,
+    target: String,
+    name: String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    if format == OutputFormat::Json {
+        return Err("export writes grammar source for another tool and has no JSON output".into());
+    }
+    let output = match target.as_str() {
+        "bison" | "yacc" => export::bison(grammar),
+        "antlr" => export::antlr(grammar, &name),
+        "tree-sitter" => export::tree_sitter(grammar, &name),
+        _ => return Err("Unknown export format".into()),
+    };
+    print!("{output}");
+
     Ok(())
 }
 
-pub fn derive(grammar: &Grammar, derive_command: String) -> Result<(), Box<dyn Error>> {
-    if derive_command.to_lowercase() == "random" {
-        let mut derivation = Derivation::new(&grammar);
+pub fn check(
+    grammar: &Grammar,
+    lines: Option<&[usize]>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let diagnostics = lint::check(grammar, lines);
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+
+    if format == OutputFormat::Json {
+        print_json(&diagnostics)?;
+    } else if diagnostics.is_empty() {
+        println!("{}", "No problems found.".green());
+    } else {
+        for diagnostic in &diagnostics {
+            let text = diagnostic.display(grammar);
+            match diagnostic.severity {
+                Severity::Error => println!("{}", text.red()),
+                Severity::Warning => println!("{}", text.yellow()),
+            }
+        }
         println!(
-            "Random Derived Word: {}",
-            derivation
-                .print_random(&grammar, Some(20))
-                .unwrap_or("No Word Generated".to_string())
-                .yellow()
+            "{} errors, {} warnings",
+            errors,
+            diagnostics.len() - errors
         );
     }
 
+    if errors > 0 {
+        return Err(format!("check found {errors} errors").into());
+    }
     Ok(())
 }
 
-pub fn tokenize(path: String){
-    let contents = fs::read_to_string(path).unwrap();
-    let mut lexer = Lexer::new(contents);
-    lexer.print_tokens();
-}
\ No newline at end of file
+pub fn graph(
+    grammar: &Grammar,
+    graph_format: String,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let graph = DependencyGraph::new(grammar);
+    if format == OutputFormat::Json {
+        return print_json(&graph);
+    }
+
+    match graph_format.as_str() {
+        "text" => print!("{}", graph.display()),
+        "dot" => print!("{}", graph.dot()),
+        _ => return Err("Unknown graph format".into()),
+    }
+    Ok(())
+}
+
+// Pumped words shown, from y^0 up to y^PUMP_TIMES.
+const PUMP_TIMES: usize = 2;
+
+pub fn pump(grammar: &Grammar, word: String, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let pumping = Pumping::find(grammar, &word)?;
+
+    if format == OutputFormat::Json {
+        let pumped: Vec<_> = (0..=PUMP_TIMES)
+            .map(|times| {
+                json!({
+                    "times": times,
+                    "word": pumping.word(times),
+                    "derivation": pumping.derivation(grammar, times),
+                })
+            })
+            .collect();
+        return print_json(&json!({
+            "nonterminal": pumping.nonterminal,
+            "decomposition": pumping.decomposition,
+            "pumped": pumped,
+        }));
+    }
+
+    let kind = match pumping.decomposition {
+        Decomposition::Regular { .. } => "Regular",
+        Decomposition::ContextFree { .. } => "Context-free",
+    };
+    println!("{} {}", "Repeated nonterminal:".green(), pumping.nonterminal);
+    println!("{} {}", format!("{kind} decomposition:").green(), pumping.decomposition.display());
+    for times in 0..=PUMP_TIMES {
+        println!("\n{} {}", format!("i = {times}:").yellow(), pumping.word(times));
+        print!("{}", pumping.derivation(grammar, times).get_history());
+    }
+
+    Ok(())
+}
+
+pub fn diff(old_path: String, new_path: String, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let old = load_grammar(&old_path)?;
+    let new = load_grammar(&new_path)?;
+    let diff = GrammarDiff::new(&old, &new);
+
+    match format {
+        OutputFormat::Text => print!("{}", diff.display()),
+        OutputFormat::Json => print_json(&diff)?,
+    }
+
+    Ok(())
+}
+
+fn build_table(grammar: &Grammar, table_kind: &str) -> Result<Table, Box<dyn Error>> {
+    match table_kind {
+        "slr" => Ok(Table::slr(grammar)?),
+        "lr1" => Ok(Table::lr1(grammar)?),
+        "lalr" => Ok(Table::lalr(grammar)?),
+        _ => Err("Unknown table kind".into()),
+    }
+}
+
+/// Reads a grammar file, or a grammar saved with `--format json` when the
+/// path ends in `.json`. The terminals and nonterminals of a JSON grammar are
+/// rebuilt from its rules rather than trusted.
+pub fn load_grammar(path: &str) -> Result<Grammar, Box<dyn Error>> {
+    load_grammar_with_lines(path).map(|(grammar, _)| grammar)
+}
+
+// Source line of each rule, missing for JSON grammars.
+type RuleLines = Option<Vec<usize>>;
+
+fn load_grammar_with_lines(path: &str) -> Result<(Grammar, RuleLines), Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    if path.ends_with(".json") {
+        return Ok((serde_json::from_str(&contents)?, None));
+    }
+    let (grammar, lines) = Grammar::parse_with_lines(&contents)?;
+    Ok((grammar, Some(lines)))
+}
+
+fn print_grammar(grammar: &Grammar, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Text => print!("{}", grammar.to_source()),
+        OutputFormat::Json => print_json(grammar)?,
+    }
+
+    Ok(())
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use serde::Serialize;
+
+use crate::grammar::{Grammar, fresh_nonterminal};
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lint {
+    UndefinedNonterminal,
+    UnreachableRule,
+    DuplicateRule,
+    AmbiguousRule,
+    LeftRecursion,
+    RecursiveStart,
+    NonTerminating,
+}
+
+/// One finding of `check`. `rule` and `line` are missing for findings about
+/// the grammar as a whole, and `line` when the grammar has no source file.
+#[derive(Serialize)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub severity: Severity,
+    pub rule: Option<usize>,
+    pub line: Option<usize>,
+    pub message: String,
+    pub help: Option<String>,
+}
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UndefinedNonterminal => "undefined-nonterminal",
+            Lint::UnreachableRule => "unreachable-rule",
+            Lint::DuplicateRule => "duplicate-rule",
+            Lint::AmbiguousRule => "ambiguous-rule",
+            Lint::LeftRecursion => "left-recursion",
+            Lint::RecursiveStart => "recursive-start",
+            Lint::NonTerminating => "non-terminating",
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            Lint::UndefinedNonterminal | Lint::NonTerminating => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn display(&self, grammar: &Grammar) -> String {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let mut result = format!("{severity}[{}]: {}\n", self.lint.name(), self.message);
+
+        match (self.rule, self.line) {
+            (Some(rule), Some(line)) => result.push_str(&format!(
+                "  --> line {line}, rule {rule}: {}\n",
+                grammar.rules[rule].display()
+            )),
+            (Some(rule), None) => result.push_str(&format!(
+                "  --> rule {rule}: {}\n",
+                grammar.rules[rule].display()
+            )),
+            _ => {}
+        }
+        if let Some(help) = &self.help {
+            result.push_str(&format!("  help: {help}\n"));
+        }
+        result
+    }
+}
+
+/// Runs every lint over the grammar. `lines` gives the source line of each
+/// rule, as returned by `Grammar::parse_with_lines`.
+pub fn check(grammar: &Grammar, lines: Option<&[usize]>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut report = |lint: Lint, rule: Option<usize>, message: String, help: Option<String>| {
+        diagnostics.push(Diagnostic {
+            lint,
+            severity: lint.severity(),
+            rule,
+            line: rule.and_then(|rule| lines.and_then(|lines| lines.get(rule).copied())),
+            message,
+            help,
+        });
+    };
+
+    let defined: String = grammar.rules.iter().map(|rule| rule.lhs).collect();
+    let generating = grammar.generating_nonterminals();
+    let reachable = grammar.reachable_nonterminals();
+    let fresh = fresh_nonterminal(&grammar.nonterminals);
+
+    if !defined.contains(grammar.start) {
+        report(
+            Lint::UndefinedNonterminal,
+            None,
+            format!("start symbol {} has no rules", grammar.start),
+            Some("add a rule for it or declare another start symbol with %start".to_string()),
+        );
+    }
+
+    for (i, rule) in grammar.rules.iter().enumerate() {
+        let mut undefined = String::new();
+        for ch in rule.rhs.chars() {
+            if ch.is_uppercase() && !defined.contains(ch) && !undefined.contains(ch) {
+                undefined.push(ch);
+                report(
+                    Lint::UndefinedNonterminal,
+                    Some(i),
+                    format!("nonterminal {ch} has no rules"),
+                    Some(format!(
+                        "add a rule `{ch} -> ...` or use a terminal instead"
+                    )),
+                );
+            }
+        }
+
+        if !reachable.contains(rule.lhs) {
+            report(
+                Lint::UnreachableRule,
+                Some(i),
+                format!(
+                    "{} cannot be reached from the start symbol {}",
+                    rule.lhs, grammar.start
+                ),
+                Some("remove the rule or use its nonterminal in a reachable rule".to_string()),
+            );
+        }
+
+        if let Some(first) = grammar.rules[..i]
+            .iter()
+            .position(|earlier| earlier.lhs == rule.lhs && earlier.rhs == rule.rhs)
+        {
+            report(
+                Lint::DuplicateRule,
+                Some(i),
+                format!("same rule as rule {first}"),
+                Some("remove one of them".to_string()),
+            );
+        }
+
+        let symbols: Vec<char> = rule.rhs.chars().collect();
+        let both_ends =
+            symbols.len() >= 2 && symbols[0] == rule.lhs && symbols[symbols.len() - 1] == rule.lhs;
+        if both_ends && grammar.rule_precedence(i).is_none() {
+            report(
+                Lint::AmbiguousRule,
+                Some(i),
+                format!("{0} at both ends of a rule for {0} makes the grammar ambiguous", rule.lhs),
+                Some(match symbols.len() {
+                    2 => format!("derive one side through a new nonterminal, e.g. {0} -> {0}X", rule.lhs),
+                    _ => "declare the operator with %left or %right, or split the rule into precedence levels"
+                        .to_string(),
+                }),
+            );
+        }
+
+        if symbols.first() == Some(&rule.lhs) {
+            report(
+                Lint::LeftRecursion,
+                Some(i),
+                format!("{} is directly left recursive", rule.lhs),
+                Some(match fresh {
+                    Some(new) => format!(
+                        "LL and recursive descent parsers cannot use it; rewrite as {0} -> β{new} with {new} -> α{new} | ε",
+                        rule.lhs
+                    ),
+                    None => "LL and recursive descent parsers cannot use it".to_string(),
+                }),
+            );
+        }
+
+        if rule.rhs.contains(grammar.start) {
+            report(
+                Lint::RecursiveStart,
+                Some(i),
+                format!(
+                    "start symbol {} appears on a right-hand side",
+                    grammar.start
+                ),
+                Some("add a new start rule that derives the current start symbol".to_string()),
+            );
+        }
+
+        // Undefined nonterminals are already reported above.
+        if let Some(stuck) = rule
+            .rhs
+            .chars()
+            .find(|&ch| ch.is_uppercase() && defined.contains(ch) && !generating.contains(ch))
+        {
+            report(
+                Lint::NonTerminating,
+                Some(i),
+                format!("rule never derives a word, {stuck} can never terminate"),
+                Some(format!(
+                    "give {stuck} a rule whose right-hand side only has terminals"
+                )),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Name, rule and line of each diagnostic, in order.
+    fn lints(source: &str) -> Vec<(&'static str, Option<usize>, Option<usize>)> {
+        let (grammar, lines) = Grammar::parse_with_lines(source).unwrap();
+        check(&grammar, Some(&lines))
+            .iter()
+            .map(|diagnostic| (diagnostic.lint.name(), diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn clean_grammar() {
+        assert!(lints("S -> aAb\nA -> c\nA ->\n").is_empty());
+    }
+
+    #[test]
+    fn undefined_nonterminal() {
+        assert_eq!(
+            lints("S -> aB\n"),
+            [("undefined-nonterminal", Some(0), Some(1))]
+        );
+        assert_eq!(
+            lints("%start T\nS -> a\n"),
+            [
+                ("undefined-nonterminal", None, None),
+                ("unreachable-rule", Some(0), Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_rule() {
+        assert_eq!(
+            lints("S -> a\n# unused\nA -> b\n"),
+            [("unreachable-rule", Some(1), Some(3))]
+        );
+    }
+
+    #[test]
+    fn duplicate_rule() {
+        assert_eq!(
+            lints("S -> a\nS -> b\nS -> a\n"),
+            [("duplicate-rule", Some(2), Some(3))]
+        );
+    }
+
+    #[test]
+    fn ambiguous_rule() {
+        assert_eq!(
+            lints("S -> T\nT -> T+T\nT -> a\n"),
+            [
+                ("ambiguous-rule", Some(1), Some(2)),
+                ("left-recursion", Some(1), Some(2))
+            ]
+        );
+        // Precedence settles the ambiguity.
+        assert_eq!(
+            lints("%left +\nS -> T\nT -> T+T\nT -> a\n"),
+            [("left-recursion", Some(1), Some(3))]
+        );
+    }
+
+    #[test]
+    fn left_recursion() {
+        let grammar = Grammar::parse("S -> E\nE -> Ea\nE -> A\nA -> b\n").unwrap();
+        let diagnostics = check(&grammar, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].lint.name(), "left-recursion");
+        assert_eq!(diagnostics[0].rule, Some(1));
+        // The suggested nonterminal is one the grammar does not use yet.
+        assert_eq!(
+            diagnostics[0].help.as_deref(),
+            Some(
+                "LL and recursive descent parsers cannot use it; rewrite as E -> βB with B -> αB | ε"
+            )
+        );
+    }
+
+    #[test]
+    fn recursive_start() {
+        assert_eq!(
+            lints("S -> aSb\nS ->\n"),
+            [("recursive-start", Some(0), Some(1))]
+        );
+    }
+
+    #[test]
+    fn non_terminating() {
+        assert_eq!(
+            lints("S -> a\nS -> A\nA -> aA\n"),
+            [
+                ("non-terminating", Some(1), Some(2)),
+                ("non-terminating", Some(2), Some(3))
+            ]
+        );
+        let grammar = Grammar::parse("S -> A\nA -> aA\n").unwrap();
+        assert!(
+            check(&grammar, None)
+                .iter()
+                .all(|diagnostic| diagnostic.severity == Severity::Error)
+        );
+    }
+}
@@ -0,0 +1,1131 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use crate::grammar::{Associativity, Derivation, Grammar, Rule, fresh_nonterminal};
+
+/// Marks the end of the input in lookaheads and ACTION tables.
+pub const END_MARKER: char = '$';
+
+/// A rule with a dot in its rhs, plus a lookahead terminal for LR(1) items.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Item {
+    pub rule: usize,
+    pub dot: usize,
+    pub lookahead: Option<char>,
+}
+
+/// Item sets and the goto graph between them. The grammar is the input
+/// grammar with `S' -> S` appended, so rule indices match the input grammar.
+pub struct Automaton {
+    pub grammar: Grammar,
+    pub accept_rule: usize,
+    pub states: Vec<BTreeSet<Item>>,
+    pub goto: Vec<BTreeMap<char, usize>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Shift(usize),
+    Reduce(usize),
+    Accept,
+}
+
+/// Several actions for the same state and lookahead. The table keeps the one
+/// yacc would pick: shift over reduce, then the earliest rule.
+pub struct Conflict {
+    pub state: usize,
+    pub symbol: char,
+    pub actions: Vec<Action>,
+    pub items: Vec<Item>,
+}
+
+/// A shift/reduce conflict settled by precedence declarations. A cell with
+/// several reduces gives one resolution per reduce, each with a `conflict`
+/// of just the shift and that reduce. `chosen` is `None` when `%nonassoc`
+/// turns the cell into a syntax error.
+pub struct Resolution {
+    pub conflict: Conflict,
+    pub chosen: Option<Action>,
+    pub reason: &'static str,
+}
+
+pub struct Table {
+    pub automaton: Automaton,
+    pub action: Vec<BTreeMap<char, Action>>,
+    pub goto: Vec<BTreeMap<char, usize>>,
+    pub conflicts: Vec<Conflict>,
+    pub resolved: Vec<Resolution>,
+}
+
+/// Parser configuration before an action: the state stack with the symbols
+/// between states, and the input left to read.
+pub struct ParseStep {
+    pub states: Vec<usize>,
+    pub symbols: String,
+    pub input: String,
+    pub action: Option<Action>,
+}
+
+/// Every step of a shift-reduce parse, and the reductions made so far.
+pub struct Trace {
+    pub steps: Vec<ParseStep>,
+    pub reductions: Vec<usize>,
+    pub error: Option<ParseError>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// No action for the symbol at `position`.
+    Unexpected {
+        position: usize,
+        symbol: char,
+        expected: Vec<char>,
+    },
+    /// Reductions at `position` that would go on forever, which a table with
+    /// conflicts can do through epsilon or unit rules.
+    Cycle {
+        position: usize,
+        state: usize,
+        rule: usize,
+    },
+}
+
+#[derive(Debug)]
+pub enum LrError {
+    ReservedEndMarker,
+    OutOfNonterminals, // No character left for the augmented start
+}
+
+// Nullable nonterminals and FIRST sets, used for FOLLOW sets and lookaheads.
+struct FirstSets {
+    nullable: String,
+    first: HashMap<char, BTreeSet<char>>,
+}
+
+impl Item {
+    pub fn display(&self, grammar: &Grammar) -> String {
+        let rule = &grammar.rules[self.rule];
+        let (before, after): (String, String) = (
+            rule.rhs.chars().take(self.dot).collect(),
+            rule.rhs.chars().skip(self.dot).collect(),
+        );
+
+        match self.lookahead {
+            Some(lookahead) => format!("[{} -> {before}•{after}, {lookahead}]", rule.lhs),
+            None => format!("{} -> {before}•{after}", rule.lhs),
+        }
+    }
+
+    // Symbol right after the dot, if any.
+    fn next_symbol(&self, grammar: &Grammar) -> Option<char> {
+        grammar.rules[self.rule].rhs.chars().nth(self.dot)
+    }
+}
+
+impl Automaton {
+    /// Canonical collection of LR(0) item sets.
+    pub fn lr0(grammar: &Grammar) -> Result<Automaton, LrError> {
+        let grammar = augment(grammar)?;
+        let accept_rule = grammar.rules.len() - 1;
+        let initial = Item {
+            rule: accept_rule,
+            dot: 0,
+            lookahead: None,
+        };
+
+        Ok(Automaton::build(grammar, initial, lr0_closure))
+    }
+
+    /// Canonical collection of LR(1) item sets.
+    pub fn lr1(grammar: &Grammar) -> Result<Automaton, LrError> {
+        let grammar = augment(grammar)?;
+        let first = FirstSets::new(&grammar);
+        let initial = Item {
+            rule: grammar.rules.len() - 1,
+            dot: 0,
+            lookahead: Some(END_MARKER),
+        };
+
+        Ok(Automaton::build(grammar, initial, |grammar, items| {
+            first.lr1_closure(grammar, items)
+        }))
+    }
+
+    /// LALR(1) item sets: LR(1) states sharing a core are merged. States are
+    /// numbered like the LR(0) automaton, which has exactly those cores.
+    pub fn lalr(grammar: &Grammar) -> Result<Automaton, LrError> {
+        let lr0 = Automaton::lr0(grammar)?;
+        let lr1 = Automaton::lr1(grammar)?;
+
+        let mut states = vec![BTreeSet::new(); lr0.states.len()];
+        for items in &lr1.states {
+            let merged = lr0
+                .state_with_core(items)
+                .expect("every LR(1) core is an LR(0) state");
+            states[merged].extend(items.iter().copied());
+        }
+
+        Ok(Automaton { states, ..lr0 })
+    }
+
+    /// The state whose items, ignoring lookaheads, are the same as `items`.
+    pub fn state_with_core(&self, items: &BTreeSet<Item>) -> Option<usize> {
+        let target = core(items);
+        self.states.iter().position(|state| core(state) == target)
+    }
+
+    // Explores every item set reachable from the initial item.
+    fn build(
+        grammar: Grammar,
+        initial: Item,
+        closure: impl Fn(&Grammar, BTreeSet<Item>) -> BTreeSet<Item>,
+    ) -> Automaton {
+        let accept_rule = grammar.rules.len() - 1;
+        let start = closure(&grammar, BTreeSet::from([initial]));
+        let mut ids = BTreeMap::from([(start.clone(), 0)]);
+        let mut states = vec![start];
+        let mut goto = Vec::new();
+
+        let mut current = 0;
+        while current < states.len() {
+            let mut kernels: BTreeMap<char, BTreeSet<Item>> = BTreeMap::new();
+            for item in &states[current] {
+                if let Some(symbol) = item.next_symbol(&grammar) {
+                    kernels.entry(symbol).or_default().insert(Item {
+                        dot: item.dot + 1,
+                        ..*item
+                    });
+                }
+            }
+
+            let mut edges = BTreeMap::new();
+            for (symbol, kernel) in kernels {
+                let target = closure(&grammar, kernel);
+                let id = match ids.get(&target) {
+                    Some(&id) => id,
+                    None => {
+                        ids.insert(target.clone(), states.len());
+                        states.push(target);
+                        states.len() - 1
+                    }
+                };
+                edges.insert(symbol, id);
+            }
+            goto.push(edges);
+            current += 1;
+        }
+
+        Automaton {
+            grammar,
+            accept_rule,
+            states,
+            goto,
+        }
+    }
+
+    /// Lists every state with its items and outgoing goto edges.
+    pub fn display(&self) -> String {
+        let mut result = String::new();
+        for (i, items) in self.states.iter().enumerate() {
+            result.push_str(&format!("State {i}:\n"));
+            for item in items {
+                result.push_str(&format!("    {}\n", item.display(&self.grammar)));
+            }
+            for (symbol, target) in &self.goto[i] {
+                result.push_str(&format!("    on {symbol} goto {target}\n"));
+            }
+        }
+        result
+    }
+}
+
+impl Table {
+    /// SLR(1) table: reduce by `A -> α` on every terminal in FOLLOW(A).
+    pub fn slr(grammar: &Grammar) -> Result<Table, LrError> {
+        let automaton = Automaton::lr0(grammar)?;
+        let follow = FirstSets::new(&automaton.grammar).follow_sets(&automaton.grammar);
+
+        Ok(Table::build(automaton, |grammar, item| {
+            follow[&grammar.rules[item.rule].lhs].iter().copied().collect()
+        }))
+    }
+
+    /// Canonical LR(1) table.
+    pub fn lr1(grammar: &Grammar) -> Result<Table, LrError> {
+        Ok(Table::build(Automaton::lr1(grammar)?, item_lookahead))
+    }
+
+    /// LALR(1) table.
+    pub fn lalr(grammar: &Grammar) -> Result<Table, LrError> {
+        Ok(Table::build(Automaton::lalr(grammar)?, item_lookahead))
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.action.len()
+    }
+
+    /// Number of filled ACTION cells.
+    pub fn action_count(&self) -> usize {
+        self.action.iter().map(|row| row.len()).sum()
+    }
+
+    /// Number of filled GOTO cells.
+    pub fn goto_count(&self) -> usize {
+        self.goto.iter().map(|row| row.len()).sum()
+    }
+
+    // Fills in ACTION and GOTO from an automaton, asking `lookaheads` which
+    // terminals each complete item reduces on.
+    fn build(automaton: Automaton, lookaheads: impl Fn(&Grammar, &Item) -> Vec<char>) -> Table {
+        let grammar = &automaton.grammar;
+        let mut action = Vec::new();
+        let mut goto = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut resolved = Vec::new();
+
+        for (state, items) in automaton.states.iter().enumerate() {
+            let mut candidates: BTreeMap<char, Vec<Action>> = BTreeMap::new();
+            let mut add = |symbol: char, new: Action| {
+                let actions = candidates.entry(symbol).or_default();
+                if !actions.contains(&new) {
+                    actions.push(new);
+                }
+            };
+
+            for item in items {
+                match item.next_symbol(grammar) {
+                    Some(symbol) if !symbol.is_uppercase() => {
+                        add(symbol, Action::Shift(automaton.goto[state][&symbol]));
+                    }
+                    Some(_) => {}
+                    None if item.rule == automaton.accept_rule => add(END_MARKER, Action::Accept),
+                    None => {
+                        for symbol in lookaheads(grammar, item) {
+                            add(symbol, Action::Reduce(item.rule));
+                        }
+                    }
+                }
+            }
+
+            let mut row = BTreeMap::new();
+            for (symbol, mut actions) in candidates {
+                // Like yacc, settle the shift against each reduce in turn. A
+                // reduce that loses is dropped, and so is a shift that loses
+                // to any reduce; `%nonassoc` makes the whole cell an error.
+                let shift = actions
+                    .iter()
+                    .copied()
+                    .find(|action| matches!(action, Action::Shift(_)));
+                let reduces: Vec<usize> = actions
+                    .iter()
+                    .filter_map(|action| match *action {
+                        Action::Reduce(rule) => Some(rule),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(shift) = shift {
+                    for rule in reduces {
+                        let Some((chosen, reason)) = resolve(grammar, symbol, shift, rule) else {
+                            continue;
+                        };
+                        match chosen {
+                            Some(Action::Shift(_)) => actions.retain(|&a| a != Action::Reduce(rule)),
+                            Some(_) => actions.retain(|&a| a != shift),
+                            None => actions.clear(),
+                        }
+                        let pair = vec![shift, Action::Reduce(rule)];
+                        resolved.push(Resolution {
+                            conflict: Conflict {
+                                state,
+                                symbol,
+                                items: conflict_items(grammar, items, symbol, &pair),
+                                actions: pair,
+                            },
+                            chosen,
+                            reason,
+                        });
+                        if actions.is_empty() {
+                            break;
+                        }
+                    }
+                }
+
+                match actions.len() {
+                    0 => {}
+                    1 => {
+                        row.insert(symbol, actions[0]);
+                    }
+                    _ => {
+                        conflicts.push(Conflict {
+                            state,
+                            symbol,
+                            actions: actions.clone(),
+                            items: conflict_items(grammar, items, symbol, &actions),
+                        });
+                        actions.sort_by_key(|action| match *action {
+                            Action::Shift(_) | Action::Accept => 0,
+                            Action::Reduce(rule) => rule + 1,
+                        });
+                        row.insert(symbol, actions[0]);
+                    }
+                }
+            }
+            action.push(row);
+
+            goto.push(
+                automaton.goto[state]
+                    .iter()
+                    .filter(|(symbol, _)| symbol.is_uppercase())
+                    .map(|(&symbol, &target)| (symbol, target))
+                    .collect(),
+            );
+        }
+
+        Table {
+            automaton,
+            action,
+            goto,
+            conflicts,
+            resolved,
+        }
+    }
+
+    /// ACTION and GOTO as a grid, one row per state. Cells with a conflict
+    /// show every candidate action.
+    pub fn display(&self) -> String {
+        let grammar = &self.automaton.grammar;
+        let augmented_start = grammar.rules[self.automaton.accept_rule].lhs;
+        let mut columns: Vec<char> = grammar.terminals.chars().collect();
+        columns.push(END_MARKER);
+        columns.extend(grammar.nonterminals.chars().filter(|&nt| nt != augmented_start));
+
+        let mut rows = vec![
+            std::iter::once("State".to_string())
+                .chain(columns.iter().map(|symbol| symbol.to_string()))
+                .collect::<Vec<String>>(),
+        ];
+        for state in 0..self.action.len() {
+            let mut row = vec![state.to_string()];
+            for &symbol in &columns {
+                let cell = if symbol.is_uppercase() {
+                    self.goto[state]
+                        .get(&symbol)
+                        .map(|target| target.to_string())
+                        .unwrap_or_default()
+                } else {
+                    match self.conflict(state, symbol) {
+                        Some(conflict) => conflict
+                            .actions
+                            .iter()
+                            .map(|action| action.to_string())
+                            .collect::<Vec<String>>()
+                            .join("/"),
+                        None => self.action[state]
+                            .get(&symbol)
+                            .map(|action| action.to_string())
+                            .unwrap_or_default(),
+                    }
+                };
+                row.push(cell);
+            }
+            rows.push(row);
+        }
+
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+            .collect();
+        let mut result = String::new();
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect();
+            result.push_str(cells.join(" | ").trim_end());
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Runs the shift-reduce parser on `word`, recording every step. Conflicted
+    /// cells use the action kept in the table.
+    ///
+    /// Actions only depend on the top state and the lookahead, so if a state
+    /// is on top again without a shift and without the stack ever being popped
+    /// below it, the parser would repeat the same reductions forever. That is
+    /// reported as a `ParseError::Cycle`.
+    pub fn trace(&self, word: &str) -> Trace {
+        let grammar = &self.automaton.grammar;
+        let input: Vec<char> = word.chars().chain(std::iter::once(END_MARKER)).collect();
+        let mut states = vec![0];
+        let mut symbols = String::new();
+        let mut position = 0;
+        let mut reduced_on: Vec<(usize, usize)> = Vec::new(); // (stack length, state) since the last shift
+        let mut trace = Trace {
+            steps: Vec::new(),
+            reductions: Vec::new(),
+            error: None,
+        };
+
+        loop {
+            let state = *states.last().unwrap();
+            let symbol = input[position];
+            let action = self.action[state].get(&symbol).copied();
+            trace.steps.push(ParseStep {
+                states: states.clone(),
+                symbols: symbols.clone(),
+                input: input[position..].iter().collect(),
+                action,
+            });
+
+            match action {
+                Some(Action::Shift(target)) => {
+                    states.push(target);
+                    symbols.push(symbol);
+                    position += 1;
+                    reduced_on.clear();
+                }
+                Some(Action::Reduce(rule_index)) => {
+                    if reduced_on.iter().any(|&(_, seen)| seen == state) {
+                        trace.error = Some(ParseError::Cycle {
+                            position,
+                            state,
+                            rule: rule_index,
+                        });
+                        break;
+                    }
+                    reduced_on.push((states.len(), state));
+
+                    let rule = &grammar.rules[rule_index];
+                    let length = rule.rhs.chars().count();
+                    states.truncate(states.len() - length);
+                    reduced_on.retain(|&(stack_length, _)| stack_length <= states.len());
+                    for _ in 0..length {
+                        symbols.pop();
+                    }
+                    let top = *states.last().unwrap();
+                    states.push(self.goto[top][&rule.lhs]);
+                    symbols.push(rule.lhs);
+                    trace.reductions.push(rule_index);
+                }
+                Some(Action::Accept) => break,
+                None => {
+                    trace.error = Some(ParseError::Unexpected {
+                        position,
+                        symbol,
+                        expected: self.action[state].keys().copied().collect(),
+                    });
+                    break;
+                }
+            }
+        }
+
+        trace
+    }
+
+    pub fn conflict(&self, state: usize, symbol: char) -> Option<&Conflict> {
+        self.conflicts
+            .iter()
+            .find(|conflict| conflict.state == state && conflict.symbol == symbol)
+    }
+}
+
+/// Which of SLR(1), LALR(1) and LR(1) the grammar belongs to, counting
+/// conflicts resolved by precedence, and the smallest table that precedence
+/// makes conflict-free if the grammar itself is not in that class.
+pub fn lalr_verdict(slr: &Table, lalr: &Table, lr1: &Table) -> String {
+    let conflict_free = |table: &Table| table.conflicts.is_empty() && table.resolved.is_empty();
+    let mut verdict = match (conflict_free(slr), conflict_free(lalr), conflict_free(lr1)) {
+        (true, _, _) => "The grammar is SLR(1).",
+        (false, true, _) => "The grammar is LALR(1) but not SLR(1).",
+        (false, false, true) => "The grammar is LR(1) but not LALR(1).",
+        (false, false, false) => "The grammar is not LR(1).",
+    }
+    .to_string();
+
+    for (name, table) in [("SLR(1)", slr), ("LALR(1)", lalr), ("LR(1)", lr1)] {
+        if conflict_free(table) {
+            break;
+        }
+        if table.conflicts.is_empty() {
+            verdict.push_str(&format!(
+                " Its {name} table has no conflicts after resolving {} by precedence.",
+                table.resolved.len()
+            ));
+            break;
+        }
+    }
+    verdict
+}
+
+/// Explains where an LALR(1) table differs from the SLR(1) and canonical
+/// LR(1) tables of the same grammar: SLR conflicts that LALR lookaheads avoid,
+/// and LALR conflicts created by merging LR(1) states.
+pub fn explain_lalr(slr: &Table, lalr: &Table, lr1: &Table) -> String {
+    let grammar = &lalr.automaton.grammar;
+    let mut result = String::new();
+
+    for conflict in &slr.conflicts {
+        if lalr.conflict(conflict.state, conflict.symbol).is_some() {
+            continue;
+        }
+        result.push_str(&format!(
+            "SLR(1) conflict in state {} on {} is avoided by LALR(1) lookaheads:\n",
+            conflict.state, conflict.symbol
+        ));
+        for action in &conflict.actions {
+            let Action::Reduce(rule) = *action else { continue };
+            let lookaheads: String = lalr.automaton.states[conflict.state]
+                .iter()
+                .filter(|item| item.rule == rule && item.next_symbol(grammar).is_none())
+                .filter_map(|item| item.lookahead)
+                .collect();
+            result.push_str(&format!(
+                "    {} reduces only on {{{}}}, though FOLLOW({}) contains {}\n",
+                grammar.rules[rule].display(),
+                lookaheads,
+                grammar.rules[rule].lhs,
+                conflict.symbol
+            ));
+        }
+    }
+
+    for conflict in &lalr.conflicts {
+        let merged: Vec<usize> = (0..lr1.state_count())
+            .filter(|&state| {
+                lalr.automaton.state_with_core(&lr1.automaton.states[state]) == Some(conflict.state)
+            })
+            .collect();
+        if merged
+            .iter()
+            .any(|&state| lr1.conflict(state, conflict.symbol).is_some())
+        {
+            continue;
+        }
+        let merged: Vec<String> = merged.iter().map(|state| state.to_string()).collect();
+        result.push_str(&format!(
+            "LALR(1) conflict in state {} on {} comes from merging LR(1) states {}\n",
+            conflict.state,
+            conflict.symbol,
+            merged.join(", ")
+        ));
+    }
+
+    result
+}
+
+impl Conflict {
+    pub fn is_shift_reduce(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|action| matches!(action, Action::Shift(_)))
+    }
+
+    /// Explains the conflict with the items that asked for each action.
+    pub fn display(&self, grammar: &Grammar) -> String {
+        let kind = if self.is_shift_reduce() {
+            "shift/reduce"
+        } else {
+            "reduce/reduce"
+        };
+        let mut result = format!(
+            "State {}: {kind} conflict on {}\n",
+            self.state, self.symbol
+        );
+        for item in &self.items {
+            result.push_str(&format!("    {}\n", item.display(grammar)));
+        }
+        result
+    }
+}
+
+impl Trace {
+    pub fn is_accepted(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The rightmost derivation of an accepted word: the reductions in reverse.
+    pub fn derivation(&self, grammar: &Grammar) -> Option<Derivation> {
+        if !self.is_accepted() {
+            return None;
+        }
+
+        let mut derivation = Derivation::new(grammar);
+        for &rule in self.reductions.iter().rev() {
+            derivation.derive_rightmost(grammar, rule).ok()?;
+        }
+        Some(derivation)
+    }
+
+    /// One line per step with the stack, the remaining input and the action taken.
+    pub fn display(&self, grammar: &Grammar) -> String {
+        let mut rows = vec![(
+            "Stack".to_string(),
+            "Input".to_string(),
+            "Action".to_string(),
+        )];
+        for step in &self.steps {
+            let mut stack = step.states[0].to_string();
+            for (symbol, state) in step.symbols.chars().zip(&step.states[1..]) {
+                stack.push_str(&format!(" {symbol} {state}"));
+            }
+            let action = match step.action {
+                Some(Action::Shift(state)) => format!("shift {state}"),
+                Some(Action::Reduce(rule)) => format!("reduce {}", grammar.rules[rule].display()),
+                Some(Action::Accept) => "accept".to_string(),
+                None => "error".to_string(),
+            };
+            rows.push((stack, step.input.clone(), action));
+        }
+
+        let stack_width = rows.iter().map(|row| row.0.chars().count()).max().unwrap_or(0);
+        let input_width = rows.iter().map(|row| row.1.chars().count()).max().unwrap_or(0);
+        let mut result = String::new();
+        for (stack, input, action) in rows {
+            result.push_str(&format!(
+                "{stack:<stack_width$} | {input:>input_width$} | {action}\n"
+            ));
+        }
+        result
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unexpected {
+                position,
+                symbol,
+                expected,
+            } => {
+                let expected: Vec<String> = expected.iter().map(|ch| ch.to_string()).collect();
+                write!(
+                    f,
+                    "unexpected {symbol} at position {position}, expected one of: {}",
+                    expected.join(" ")
+                )
+            }
+            ParseError::Cycle {
+                position,
+                state,
+                rule,
+            } => write!(
+                f,
+                "reductions never end at position {position}: state {state} would reduce by rule {rule} again without reading input"
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl Resolution {
+    pub fn display(&self, grammar: &Grammar) -> String {
+        let chosen = match self.chosen {
+            Some(Action::Shift(_)) => "shift".to_string(),
+            Some(Action::Reduce(rule)) => format!("reduce {}", grammar.rules[rule].display()),
+            Some(Action::Accept) => "accept".to_string(),
+            None => "error".to_string(),
+        };
+        format!(
+            "State {}: shift/reduce conflict on {} resolved as {chosen} ({})\n",
+            self.conflict.state, self.conflict.symbol, self.reason
+        )
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Shift(state) => write!(f, "s{state}"),
+            Action::Reduce(rule) => write!(f, "r{rule}"),
+            Action::Accept => write!(f, "acc"),
+        }
+    }
+}
+
+impl fmt::Display for LrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LrError::ReservedEndMarker => {
+                write!(f, "{END_MARKER} is reserved as the end marker and cannot be a terminal")
+            }
+            LrError::OutOfNonterminals => {
+                write!(f, "every uppercase character is used, none is left for the augmented start")
+            }
+        }
+    }
+}
+
+impl Error for LrError {}
+
+impl FirstSets {
+    fn new(grammar: &Grammar) -> FirstSets {
+        let mut sets = FirstSets {
+            nullable: String::new(),
+            first: grammar
+                .nonterminals
+                .chars()
+                .map(|nt| (nt, BTreeSet::new()))
+                .collect(),
+        };
+
+        loop {
+            let mut changed = false;
+            for rule in &grammar.rules {
+                let (first, nullable) = sets.of(&rule.rhs);
+                if nullable && !sets.nullable.contains(rule.lhs) {
+                    sets.nullable.push(rule.lhs);
+                    changed = true;
+                }
+                let entry = sets.first.get_mut(&rule.lhs).unwrap();
+                for symbol in first {
+                    changed |= entry.insert(symbol);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        sets
+    }
+
+    // Adds `[B -> •γ, b]` for each `[A -> α•Bβ, a]` and each b in FIRST(βa).
+    fn lr1_closure(&self, grammar: &Grammar, items: BTreeSet<Item>) -> BTreeSet<Item> {
+        let mut closure = items;
+        let mut pending: Vec<Item> = closure.iter().copied().collect();
+
+        while let Some(item) = pending.pop() {
+            let Some(symbol) = item.next_symbol(grammar) else { continue };
+            if !symbol.is_uppercase() {
+                continue;
+            }
+
+            let rest: String = grammar.rules[item.rule].rhs.chars().skip(item.dot + 1).collect();
+            let (mut lookaheads, nullable) = self.of(&rest);
+            if nullable {
+                lookaheads.extend(item.lookahead);
+            }
+
+            for rule in grammar.rule_idxs_from_nt(symbol) {
+                for &lookahead in &lookaheads {
+                    let new = Item {
+                        rule: rule as usize,
+                        dot: 0,
+                        lookahead: Some(lookahead),
+                    };
+                    if closure.insert(new) {
+                        pending.push(new);
+                    }
+                }
+            }
+        }
+
+        closure
+    }
+
+    // FIRST of a string of symbols, and whether the whole string is nullable.
+    fn of(&self, symbols: &str) -> (BTreeSet<char>, bool) {
+        let mut first = BTreeSet::new();
+        for symbol in symbols.chars() {
+            if !symbol.is_uppercase() {
+                first.insert(symbol);
+                return (first, false);
+            }
+            first.extend(&self.first[&symbol]);
+            if !self.nullable.contains(symbol) {
+                return (first, false);
+            }
+        }
+        (first, true)
+    }
+
+    // FOLLOW sets for the augmented grammar, whose start is followed by the end marker.
+    fn follow_sets(&self, grammar: &Grammar) -> HashMap<char, BTreeSet<char>> {
+        let mut follow: HashMap<char, BTreeSet<char>> = grammar
+            .nonterminals
+            .chars()
+            .map(|nt| (nt, BTreeSet::new()))
+            .collect();
+        follow.get_mut(&grammar.start).unwrap().insert(END_MARKER);
+
+        loop {
+            let mut changed = false;
+            for rule in &grammar.rules {
+                for (i, symbol) in rule.rhs.char_indices() {
+                    if !symbol.is_uppercase() {
+                        continue;
+                    }
+                    let (mut after, nullable) = self.of(&rule.rhs[i + symbol.len_utf8()..]);
+                    if nullable {
+                        after.extend(follow[&rule.lhs].clone());
+                    }
+                    let entry = follow.get_mut(&symbol).unwrap();
+                    for terminal in after {
+                        changed |= entry.insert(terminal);
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        follow
+    }
+}
+
+// Appends `S' -> S` with a fresh S'.
+fn augment(grammar: &Grammar) -> Result<Grammar, LrError> {
+    if grammar.terminals.contains(END_MARKER) {
+        return Err(LrError::ReservedEndMarker);
+    }
+
+    let start = fresh_nonterminal(&grammar.nonterminals).ok_or(LrError::OutOfNonterminals)?;
+    let mut rules = grammar.rules.clone();
+    rules.push(Rule::new(start, &grammar.start.to_string()));
+    let mut augmented = Grammar::with_start(start, rules);
+    augmented.precedence = grammar.precedence.clone();
+    Ok(augmented)
+}
+
+// Settles a shift against a reduce the way yacc does, when both the
+// lookahead and the rule have a declared precedence.
+fn resolve(
+    grammar: &Grammar,
+    symbol: char,
+    shift: Action,
+    rule: usize,
+) -> Option<(Option<Action>, &'static str)> {
+    let (token_level, associativity) = grammar.terminal_precedence(symbol)?;
+    let (rule_level, _) = grammar.rule_precedence(rule)?;
+    let reduce = Action::Reduce(rule);
+
+    Some(if rule_level > token_level {
+        (Some(reduce), "rule has higher precedence")
+    } else if rule_level < token_level {
+        (Some(shift), "lookahead has higher precedence")
+    } else {
+        match associativity {
+            Associativity::Left => (Some(reduce), "left associative"),
+            Associativity::Right => (Some(shift), "right associative"),
+            Associativity::NonAssoc => (None, "nonassociative"),
+        }
+    })
+}
+
+fn lr0_closure(grammar: &Grammar, items: BTreeSet<Item>) -> BTreeSet<Item> {
+    let mut closure = items;
+    let mut pending: Vec<Item> = closure.iter().copied().collect();
+
+    while let Some(item) = pending.pop() {
+        let Some(symbol) = item.next_symbol(grammar) else { continue };
+        for rule in grammar.rule_idxs_from_nt(symbol) {
+            let new = Item {
+                rule: rule as usize,
+                dot: 0,
+                lookahead: None,
+            };
+            if closure.insert(new) {
+                pending.push(new);
+            }
+        }
+    }
+
+    closure
+}
+
+fn core(items: &BTreeSet<Item>) -> BTreeSet<(usize, usize)> {
+    items.iter().map(|item| (item.rule, item.dot)).collect()
+}
+
+fn item_lookahead(_: &Grammar, item: &Item) -> Vec<char> {
+    item.lookahead.into_iter().collect()
+}
+
+// Items of a state responsible for the actions of a conflict.
+fn conflict_items(
+    grammar: &Grammar,
+    items: &BTreeSet<Item>,
+    symbol: char,
+    actions: &[Action],
+) -> Vec<Item> {
+    items
+        .iter()
+        .filter(|item| match item.next_symbol(grammar) {
+            Some(next) => next == symbol && actions.iter().any(|a| matches!(a, Action::Shift(_))),
+            None => {
+                actions.contains(&Action::Reduce(item.rule))
+                    && item.lookahead.is_none_or(|lookahead| lookahead == symbol)
+            }
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(source: &str) -> Grammar {
+        Grammar::parse(source).unwrap()
+    }
+
+    // The classic assignment grammar: FOLLOW(R) contains `=`, so SLR(1) has a
+    // shift/reduce conflict on it that LALR(1) lookaheads avoid.
+    const ASSIGNMENT: &str = "S -> L=R\nS -> R\nL -> *R\nL -> i\nR -> L\n";
+
+    #[test]
+    fn lalr_but_not_slr() {
+        let grammar = grammar(ASSIGNMENT);
+        let slr = Table::slr(&grammar).unwrap();
+        let lalr = Table::lalr(&grammar).unwrap();
+        let lr1 = Table::lr1(&grammar).unwrap();
+
+        assert_eq!(slr.conflicts.len(), 1);
+        assert_eq!(slr.conflicts[0].symbol, '=');
+        assert!(slr.conflicts[0].is_shift_reduce());
+        assert!(lalr.conflicts.is_empty());
+        assert!(lr1.conflicts.is_empty());
+        assert_eq!(lalr.state_count(), slr.state_count());
+        assert!(lr1.state_count() > lalr.state_count());
+
+        let explanation = explain_lalr(&slr, &lalr, &lr1);
+        assert!(explanation.contains("avoided by LALR(1) lookaheads"));
+    }
+
+    #[test]
+    fn lr1_but_not_lalr() {
+        let grammar = grammar("S -> aAd\nS -> bBd\nS -> aBe\nS -> bAe\nA -> c\nB -> c\n");
+        let lalr = Table::lalr(&grammar).unwrap();
+        let lr1 = Table::lr1(&grammar).unwrap();
+
+        assert!(lr1.conflicts.is_empty());
+        assert!(!lalr.conflicts.is_empty());
+        assert!(
+            lalr.conflicts
+                .iter()
+                .all(|conflict| !conflict.is_shift_reduce())
+        );
+    }
+
+    #[test]
+    fn trace_accepts_and_rejects() {
+        let grammar = grammar(ASSIGNMENT);
+        let table = Table::lalr(&grammar).unwrap();
+
+        let trace = table.trace("*i=i");
+        assert!(trace.is_accepted());
+        assert_eq!(trace.derivation(&grammar).unwrap().word(), "*i=i");
+
+        let trace = table.trace("i=");
+        assert!(matches!(
+            trace.error,
+            Some(ParseError::Unexpected {
+                position: 2,
+                symbol: END_MARKER,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn trace_stops_endless_reductions() {
+        let grammar = grammar("S ->\nS -> SS\nS -> a\n");
+        let table = Table::lalr(&grammar).unwrap();
+        assert!(!table.conflicts.is_empty());
+
+        let trace = table.trace("aa");
+        assert!(matches!(
+            trace.error,
+            Some(ParseError::Cycle { position: 2, .. })
+        ));
+    }
+
+    // The resolutions made on `symbol`, as (chosen action, reason).
+    fn resolutions(table: &Table, symbol: char) -> Vec<(Option<Action>, &'static str)> {
+        table
+            .resolved
+            .iter()
+            .filter(|resolution| resolution.conflict.symbol == symbol)
+            .map(|resolution| (resolution.chosen, resolution.reason))
+            .collect()
+    }
+
+    #[test]
+    fn associativity() {
+        let sum = "E -> E+E\nE -> a\n";
+
+        let table = Table::lalr(&grammar(&format!("%left +\n{sum}"))).unwrap();
+        assert!(table.conflicts.is_empty());
+        assert_eq!(
+            resolutions(&table, '+'),
+            [(Some(Action::Reduce(0)), "left associative")]
+        );
+
+        let table = Table::lalr(&grammar(&format!("%right +\n{sum}"))).unwrap();
+        assert!(table.conflicts.is_empty());
+        assert!(matches!(
+            resolutions(&table, '+')[..],
+            [(Some(Action::Shift(_)), "right associative")]
+        ));
+
+        let table = Table::lalr(&grammar(&format!("%nonassoc +\n{sum}"))).unwrap();
+        assert!(table.conflicts.is_empty());
+        assert_eq!(resolutions(&table, '+'), [(None, "nonassociative")]);
+        let state = table.resolved[0].conflict.state;
+        assert!(!table.action[state].contains_key(&'+'));
+        assert!(!table.trace("a+a+a").is_accepted());
+        assert!(table.trace("a+a").is_accepted());
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let grammar = grammar("%left +\n%left *\nE -> E+E\nE -> E*E\nE -> a\n");
+        let table = Table::lalr(&grammar).unwrap();
+        assert!(table.conflicts.is_empty());
+
+        // After E+E, `*` binds tighter and is shifted; after E*E, the
+        // multiplication is reduced before a `+`.
+        let plus = resolutions(&table, '+');
+        assert!(plus.contains(&(Some(Action::Reduce(0)), "left associative")));
+        assert!(plus.contains(&(Some(Action::Reduce(1)), "rule has higher precedence")));
+        let times = resolutions(&table, '*');
+        assert!(times.contains(&(Some(Action::Reduce(1)), "left associative")));
+        assert!(
+            times
+                .iter()
+                .any(|&(chosen, reason)| matches!(chosen, Some(Action::Shift(_)))
+                    && reason == "lookahead has higher precedence")
+        );
+
+        let trace = table.trace("a+a*a");
+        assert!(trace.is_accepted());
+        // The sum is the outermost rule, so a*a is grouped first.
+        let derivation = trace.derivation(&grammar).unwrap();
+        assert_eq!(derivation.steps[1].0, 0);
+    }
+
+    #[test]
+    fn verdict_counts_precedence() {
+        let expression = grammar("%left +\n%left *\nE -> E+E\nE -> E*E\nE -> a\n");
+        let slr = Table::slr(&expression).unwrap();
+        let lalr = Table::lalr(&expression).unwrap();
+        let lr1 = Table::lr1(&expression).unwrap();
+        assert_eq!(
+            lalr_verdict(&slr, &lalr, &lr1),
+            "The grammar is not LR(1). Its SLR(1) table has no conflicts after resolving 4 by precedence."
+        );
+
+        let assignment = grammar(ASSIGNMENT);
+        let slr = Table::slr(&assignment).unwrap();
+        let lalr = Table::lalr(&assignment).unwrap();
+        let lr1 = Table::lr1(&assignment).unwrap();
+        assert_eq!(
+            lalr_verdict(&slr, &lalr, &lr1),
+            "The grammar is LALR(1) but not SLR(1)."
+        );
+    }
+}
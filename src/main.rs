@@ -48,13 +48,13 @@ fn example_manual() {
         "Random Derived Word (5 Step Limit): {}",
         derivation
             .print_random(&grammar, Some(5))
-            .unwrap_or("No Word Generated".to_string())
+            .unwrap_or_else(|error| format!("No Word Generated ({error})"))
     );
     derivation = Derivation::new(&grammar);
     println!(
         "Random Derived Word (No Step Limit): {}",
         derivation
             .print_random(&grammar, None)
-            .unwrap_or("No Word Generated".to_string())
+            .unwrap_or_else(|error| format!("No Word Generated ({error})"))
     );
 }
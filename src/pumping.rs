@@ -0,0 +1,435 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::grammar::{Derivation, Grammar};
+
+/// A parse tree over a word. `start..end` is the span of the word, in
+/// characters, derived by the node; children are the subtrees of the rule's
+/// nonterminals, in order.
+pub struct ParseTree {
+    pub rule: usize,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<ParseTree>,
+}
+
+#[derive(Serialize)]
+pub enum Decomposition {
+    /// w = xyz for a regular grammar, with xy^iz in the language for all i.
+    Regular { x: String, y: String, z: String },
+    /// w = uvwxy for a context-free grammar, with uv^iwx^iy in the language.
+    ContextFree {
+        u: String,
+        v: String,
+        w: String,
+        x: String,
+        y: String,
+    },
+}
+
+/// A decomposition of a word found from a nonterminal that repeats on a path
+/// of its parse tree, along with the rules needed to pump it.
+pub struct Pumping {
+    pub nonterminal: char,
+    pub decomposition: Decomposition,
+    pieces: [String; 5],
+    rules: [Vec<usize>; 5], // Leftmost derivation, split like `pieces`
+}
+
+#[derive(Debug)]
+pub enum PumpError {
+    NotGenerated,
+    NoRepetition,
+}
+
+// Rule and child spans of each (nonterminal, start, end) that derives
+// word[start..end].
+type SpanTable = HashMap<(char, usize, usize), (usize, Vec<(usize, usize)>)>;
+
+// A parse tree node flattened in preorder, which is also the order of its
+// rule in the leftmost derivation.
+struct Node {
+    nonterminal: char,
+    start: usize,
+    end: usize,
+    size: usize, // Nodes in the subtree, itself included
+    depth: usize,
+    parent: Option<usize>,
+}
+
+impl ParseTree {
+    /// Finds a parse tree for the word, or None if the grammar does not
+    /// generate it. Works bottom-up over spans like CYK, so it handles any
+    /// context-free grammar including epsilon and unit rules.
+    pub fn find(grammar: &Grammar, word: &str) -> Option<ParseTree> {
+        let word: Vec<char> = word.chars().collect();
+        let mut found = SpanTable::new();
+
+        for length in 0..=word.len() {
+            for start in 0..=word.len() - length {
+                let end = start + length;
+                // Unit and epsilon rules can make a span depend on itself, so
+                // repeat until nothing new is found. An entry only refers to
+                // entries found before it, which keeps the trees finite.
+                loop {
+                    let mut changed = false;
+                    for (i, rule) in grammar.rules.iter().enumerate() {
+                        if found.contains_key(&(rule.lhs, start, end)) {
+                            continue;
+                        }
+                        let rhs: Vec<char> = rule.rhs.chars().collect();
+                        let mut spans = Vec::new();
+                        if split(&rhs, &word, &found, start, end, &mut spans) {
+                            found.insert((rule.lhs, start, end), (i, spans));
+                            changed = true;
+                        }
+                    }
+                    if !changed {
+                        break;
+                    }
+                }
+            }
+        }
+
+        build(grammar, &found, grammar.start, 0, word.len())
+    }
+
+    /// Rule indices in preorder, which is the leftmost derivation.
+    pub fn rules(&self) -> Vec<usize> {
+        let mut rules = vec![self.rule];
+        for child in &self.children {
+            rules.extend(child.rules());
+        }
+        rules
+    }
+
+    fn flatten(
+        &self,
+        grammar: &Grammar,
+        depth: usize,
+        parent: Option<usize>,
+        nodes: &mut Vec<Node>,
+    ) {
+        let index = nodes.len();
+        nodes.push(Node {
+            nonterminal: grammar.rules[self.rule].lhs,
+            start: self.start,
+            end: self.end,
+            size: 0,
+            depth,
+            parent,
+        });
+        for child in &self.children {
+            child.flatten(grammar, depth + 1, Some(index), nodes);
+        }
+        nodes[index].size = nodes.len() - index;
+    }
+}
+
+impl Pumping {
+    /// Finds a nonterminal A that occurs twice on a path of the word's parse
+    /// tree, where the outer A derives more of the word than the inner one.
+    ///
+    /// For a regular grammar (all rules right-linear or all left-linear) the
+    /// first repetition along the word from the left is used, as in the lemma
+    /// for automata, giving xyz with the shortest xy. In a right-linear tree
+    /// that is the repetition nearest the root, in a left-linear one the one
+    /// nearest the leaves. Otherwise the repetition with the shortest vwx is
+    /// used.
+    pub fn find(grammar: &Grammar, word: &str) -> Result<Pumping, PumpError> {
+        let tree = ParseTree::find(grammar, word).ok_or(PumpError::NotGenerated)?;
+        let mut nodes = Vec::new();
+        tree.flatten(grammar, 0, None, &mut nodes);
+        let right_linear = is_linear(grammar, true);
+        let left_linear = !right_linear && is_linear(grammar, false);
+        let regular = right_linear || left_linear;
+
+        let mut best: Option<(usize, usize)> = None;
+        for (inner, node) in nodes.iter().enumerate() {
+            let mut ancestor = node.parent;
+            while let Some(outer) = ancestor {
+                let candidate = &nodes[outer];
+                if candidate.nonterminal == node.nonterminal
+                    && candidate.end - candidate.start > node.end - node.start
+                {
+                    let better = match best {
+                        None => true,
+                        Some((best_outer, best_inner)) if right_linear => {
+                            (node.depth, usize::MAX - candidate.depth)
+                                < (
+                                    nodes[best_inner].depth,
+                                    usize::MAX - nodes[best_outer].depth,
+                                )
+                        }
+                        Some((best_outer, best_inner)) if left_linear => {
+                            (node.depth, candidate.depth)
+                                > (nodes[best_inner].depth, nodes[best_outer].depth)
+                        }
+                        Some((best_outer, best_inner)) => {
+                            let length = |i: usize| nodes[i].end - nodes[i].start;
+                            (length(outer), node.depth - candidate.depth)
+                                < (
+                                    length(best_outer),
+                                    nodes[best_inner].depth - nodes[best_outer].depth,
+                                )
+                        }
+                    };
+                    if better {
+                        best = Some((outer, inner));
+                    }
+                }
+                ancestor = candidate.parent;
+            }
+        }
+        let (outer, inner) = best.ok_or(PumpError::NoRepetition)?;
+
+        let chars: Vec<char> = word.chars().collect();
+        let (outer_node, inner_node) = (&nodes[outer], &nodes[inner]);
+        let cuts = [
+            0,
+            outer_node.start,
+            inner_node.start,
+            inner_node.end,
+            outer_node.end,
+            chars.len(),
+        ];
+        let pieces: [String; 5] =
+            std::array::from_fn(|i| chars[cuts[i]..cuts[i + 1]].iter().collect());
+
+        let all_rules = tree.rules();
+        let rule_cuts = [
+            0,
+            outer,
+            inner,
+            inner + inner_node.size,
+            outer + outer_node.size,
+            all_rules.len(),
+        ];
+        let rules: [Vec<usize>; 5] =
+            std::array::from_fn(|i| all_rules[rule_cuts[i]..rule_cuts[i + 1]].to_vec());
+
+        let [u, v, w, x, y] = pieces.clone();
+        let decomposition = if !regular {
+            Decomposition::ContextFree { u, v, w, x, y }
+        } else if x.is_empty() {
+            Decomposition::Regular {
+                x: u,
+                y: v,
+                z: w + &y,
+            }
+        } else {
+            Decomposition::Regular {
+                x: u + &w,
+                y: x,
+                z: y,
+            }
+        };
+
+        Ok(Pumping {
+            nonterminal: inner_node.nonterminal,
+            decomposition,
+            pieces,
+            rules,
+        })
+    }
+
+    /// The word with the repeated part taken `times` times.
+    pub fn word(&self, times: usize) -> String {
+        let [u, v, w, x, y] = &self.pieces;
+        format!("{u}{}{w}{}{y}", v.repeat(times), x.repeat(times))
+    }
+
+    /// Leftmost derivation of `word(times)`: the rules between the outer and
+    /// inner nonterminal are repeated the same way.
+    pub fn derivation(&self, grammar: &Grammar, times: usize) -> Derivation {
+        let [before, down, inner, up, after] = &self.rules;
+        let mut rules = before.clone();
+        for _ in 0..times {
+            rules.extend(down);
+        }
+        rules.extend(inner);
+        for _ in 0..times {
+            rules.extend(up);
+        }
+        rules.extend(after);
+
+        let mut derivation = Derivation::new(grammar);
+        derivation
+            .replay(grammar, &rules)
+            .expect("pumped rules come from a parse tree");
+        derivation
+    }
+}
+
+impl Decomposition {
+    pub fn display(&self) -> String {
+        let parts: Vec<(&str, &String)> = match self {
+            Decomposition::Regular { x, y, z } => vec![("x", x), ("y", y), ("z", z)],
+            Decomposition::ContextFree { u, v, w, x, y } => {
+                vec![("u", u), ("v", v), ("w", w), ("x", x), ("y", y)]
+            }
+        };
+        let parts: Vec<String> = parts
+            .iter()
+            .map(|(name, part)| format!("{name} = \"{part}\""))
+            .collect();
+        parts.join(", ")
+    }
+}
+
+impl fmt::Display for PumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PumpError::NotGenerated => write!(f, "the grammar does not generate the word"),
+            PumpError::NoRepetition => write!(
+                f,
+                "no nonterminal repeats on a path of the parse tree, the word is too short to pump"
+            ),
+        }
+    }
+}
+
+impl Error for PumpError {}
+
+// Matches rhs symbols against word[start..end], pushing the span of each
+// nonterminal.
+fn split(
+    rhs: &[char],
+    word: &[char],
+    found: &SpanTable,
+    start: usize,
+    end: usize,
+    spans: &mut Vec<(usize, usize)>,
+) -> bool {
+    let Some((&symbol, rest)) = rhs.split_first() else {
+        return start == end;
+    };
+
+    if !symbol.is_uppercase() {
+        return start < end
+            && word[start] == symbol
+            && split(rest, word, found, start + 1, end, spans);
+    }
+    for middle in start..=end {
+        if found.contains_key(&(symbol, start, middle)) {
+            spans.push((start, middle));
+            if split(rest, word, found, middle, end, spans) {
+                return true;
+            }
+            spans.pop();
+        }
+    }
+    false
+}
+
+fn build(
+    grammar: &Grammar,
+    found: &SpanTable,
+    nonterminal: char,
+    start: usize,
+    end: usize,
+) -> Option<ParseTree> {
+    let (rule, spans) = found.get(&(nonterminal, start, end))?;
+    let nonterminals = grammar.rules[*rule]
+        .rhs
+        .chars()
+        .filter(|ch| ch.is_uppercase());
+
+    let mut children = Vec::new();
+    for (nt, &(child_start, child_end)) in nonterminals.zip(spans) {
+        children.push(build(grammar, found, nt, child_start, child_end)?);
+    }
+    Some(ParseTree {
+        rule: *rule,
+        start,
+        end,
+        children,
+    })
+}
+
+// Every rhs has at most one nonterminal, always last (right-linear) or
+// always first (left-linear).
+fn is_linear(grammar: &Grammar, at_end: bool) -> bool {
+    grammar.rules.iter().all(|rule| {
+        let nonterminals: Vec<usize> = rule
+            .rhs
+            .chars()
+            .enumerate()
+            .filter(|(_, ch)| ch.is_uppercase())
+            .map(|(i, _)| i)
+            .collect();
+        match nonterminals[..] {
+            [] => true,
+            [i] if at_end => i == rule.rhs.chars().count() - 1,
+            [i] => i == 0,
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pumps `word` and checks the pumped words against their derivations.
+    fn pump(source: &str, word: &str) -> Pumping {
+        let grammar = Grammar::parse(source).unwrap();
+        let pumping = Pumping::find(&grammar, word).unwrap();
+        assert_eq!(pumping.word(1), word);
+        for times in 0..=2 {
+            assert_eq!(
+                pumping.derivation(&grammar, times).word(),
+                pumping.word(times)
+            );
+        }
+        pumping
+    }
+
+    #[test]
+    fn right_linear() {
+        let pumping = pump("S -> aS\nS -> b\n", "aaab");
+        assert_eq!(
+            pumping.decomposition.display(),
+            "x = \"\", y = \"a\", z = \"aab\""
+        );
+        assert_eq!(pumping.word(0), "aab");
+        assert_eq!(pumping.word(2), "aaaab");
+    }
+
+    #[test]
+    fn left_linear() {
+        let pumping = pump("S -> Sa\nS -> b\n", "baaa");
+        assert_eq!(
+            pumping.decomposition.display(),
+            "x = \"b\", y = \"a\", z = \"aa\""
+        );
+        assert_eq!(pumping.word(0), "baa");
+    }
+
+    #[test]
+    fn context_free() {
+        let pumping = pump("S -> aSb\nS ->\n", "aabb");
+        assert_eq!(pumping.nonterminal, 'S');
+        assert_eq!(
+            pumping.decomposition.display(),
+            "u = \"a\", v = \"a\", w = \"\", x = \"b\", y = \"b\""
+        );
+        assert_eq!(pumping.word(0), "ab");
+        assert_eq!(pumping.word(2), "aaabbb");
+    }
+
+    #[test]
+    fn errors() {
+        let grammar = Grammar::parse("S -> aS\nS -> b\n").unwrap();
+        assert!(matches!(
+            Pumping::find(&grammar, "ba"),
+            Err(PumpError::NotGenerated)
+        ));
+        assert!(matches!(
+            Pumping::find(&grammar, "b"),
+            Err(PumpError::NoRepetition)
+        ));
+    }
+}